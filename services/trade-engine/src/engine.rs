@@ -67,6 +67,9 @@ pub fn swap(chan: Chan, mut trade:Trade, cache:&mut Cache){
 /// pool init - https://solscan.io/token/W2tX3GxsVH6Jng4UfaaUkgsHqU1c1sTeTaiAG4Npump?time=1735321560000&time=1735321679000&page=5#defiactivities
 /// pool swap - https://solscan.io/tx/SggjhnEzULzofBb6njNaaFP7T31z6uddrx2ibafA6FhpQxuifEoYh2WiRWgg5geysqRkiAuhS7esgDMxLxtmTp5
 /// SwapBaseOutLog { log_type: 4, max_in: 2955714285, amount_out: 2364571428, direction: 1, user_source: 10000000, pool_coin: 171880473738872568, pool_pc: 843000100000, deduct_in: 11628 }
+///
+/// Buys on the first log and sells on `cfg.tp` / `cfg.sl` only. Pluggable strategies
+/// run in wolf_trader's `trade_chan`, see `wolf_trader::strategy`.
 pub async fn trade_chan(chan: Chan, mut rec: Receiver<InternalCommand>) {
     let mut heartbeat = Instant::now();
     let m = mongo().await;
@@ -396,17 +399,6 @@ pub async fn trade_chan(chan: Chan, mut rec: Receiver<InternalCommand>) {
                     info!("price {}", trade.price);
                     // info!("pump price = {}",trade.price);
 
-                    // // To introduce sending dynamic rust code
-                    // let mut buy = false;
-                    // if let ProgramLogInfo::PumpTradeLog(d) = trade.plog.log {
-                    //     if d.sol_amount >= LAMPORTS_PER_SOL*1 &&
-                    //         d.token_amount.to_f64().unwrap() >= (37951768488745.0 * 0.95)  &&
-                    //         d.token_amount.to_f64().unwrap() >= (37951768488745.0 * 1.05)
-                    //     {
-                    //         buy = true;
-                    //     }
-                    // }
-
                     if trade.internal_unchecked().rpc_status == RpcState::Free
                         && trade.state == TradeState::Buy
                     {
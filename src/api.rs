@@ -1,11 +1,16 @@
 use crate::bg_chan::bg_chan;
+use crate::blockhash::blockhash_chan;
 use crate::chan::Chan;
 use crate::constant::*;
 use crate::trade::*;
+use crate::jito_chan::jito_chan;
 use crate::trade_chan::trade_chan;
 use crate::trade_cmd::InternalCommand;
 use crate::user::*;
+use crate::wallet;
+use crate::wallet::pick_wallets;
 use anyhow::anyhow;
+use log::{error, info};
 use axum::extract::State;
 use axum::http::StatusCode;
 use axum::routing::{get, post};
@@ -16,8 +21,11 @@ use axum_extra::TypedHeader;
 use axum_server::tls_rustls::RustlsConfig;
 use mongodb::Collection;
 use redis::aio::ConnectionManager;
+use rust_decimal::Decimal;
 use serde_json::{json, Value};
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
 use solana_sdk::signature::Keypair;
+use solana_sdk::signer::Signer;
 use std::net::SocketAddr;
 use std::sync::atomic::AtomicI64;
 use std::sync::atomic::Ordering::SeqCst;
@@ -80,12 +88,28 @@ pub async fn start(port:u16)  {
         }
     });
 
+    tokio::spawn({
+        let chan = chan.clone();
+        async move {
+            jito_chan(chan).await;
+        }
+    });
+
+    tokio::spawn(async move {
+        blockhash_chan().await
+    });
+
     let cors_layer = tower_http::cors::CorsLayer::permissive();
     let app = Router::new()
         .route("/users", post(login).get(get_users))
         .route("/users/{id}", get(get_user).delete(delete_user))
         .route("/trades/{id}", get(get_trade))
+        .route("/trades/{id}/sell", post(sell_trade))
+        .route("/trades/{id}/add", post(add_to_trade))
         .route("/buy", post(buy))
+        .route("/wallets", post(add_wallet).get(get_wallets))
+        .route("/wallets/fund", post(fund_wallet))
+        .route("/wallets/sweep", post(sweep_wallets))
         .route("/feedbacks", post(create_feedback).get(get_feedbacks))
         .with_state(ServerState {
             chan,
@@ -111,18 +135,145 @@ async fn buy(
     Json(payload): Json<BuyPrompt>,
 ) -> Result<Json<Value>,HttpErrorResponse> {
     let user = get_user_from_auth(auth,&state.user_db).await?;
-    let kp = Keypair::from_base58_string(user.private_key.as_str());
+    if let Some(limit) = &payload.limit {
+        // neither condition can hold, the order would only wait for its expiry
+        if limit.price.is_none() && limit.tvl.is_none() {
+            return Err((StatusCode::BAD_REQUEST,"limit needs a price or a tvl".to_string()));
+        }
+    }
+    let split = payload.split.unwrap_or(1);
+    let selection = payload.wallet.clone().unwrap_or(user.wallet_selection.clone());
+    let wallets = pick_wallets(&user, &state.user_db, &state.trade_db, &state.chan, &payload.mint, &selection, split)
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
     let mut mint = Trade::db_get_mint_red(&mut state.red, payload.mint.as_str())
         .await
         .map_err(internal_error)?;
-    mint.root_kp = kp.to_bytes().to_vec();
+    mint.user_id = user.id.to_string();
     mint.cfg.tp = payload.tp;
     mint.cfg.sl = payload.sl;
-    mint.cfg.max_sol = payload.sol_ui;
-    let id = Trade::db_id_inc_red(&mut state.red).await;
-    mint.id = id;
-    state.chan.trade.try_send(InternalCommand::SwapRequest(mint)).unwrap();
-    Ok(Json(json!({"trade_id":id})))
+    mint.cfg.max_sol = payload.sol_ui / Decimal::from(wallets.len());
+    if let Some(trailing_sl) = payload.trailing_sl {
+        mint.cfg.trailing_sl = trailing_sl;
+    }
+    if let Some(levels) = &payload.tp_ladder {
+        mint.cfg.set_tp_ladder(levels);
+    }
+    if let Some(policy) = payload.jito_tip_policy {
+        mint.cfg.jito_tip_policy = policy;
+    }
+    mint.cfg.jito_bundle = payload.jito_bundle.unwrap_or(false);
+    mint.cfg.simulate = payload.simulate.unwrap_or(false);
+    mint.cfg.versioned = payload.versioned.unwrap_or(false);
+    mint.cfg.durable_nonce = payload.durable_nonce.unwrap_or(false);
+    mint.strategy = payload.strategy.or(user.strategy);
+    mint.limit = payload.limit;
+    mint.paper = payload.paper.unwrap_or(false);
+    let mut ids = vec![];
+    for kp in wallets {
+        let mut trade = mint.clone();
+        trade.root_kp = kp.to_bytes().to_vec();
+        let id = Trade::db_id_inc_red(&mut state.red).await;
+        trade.id = id;
+        ids.push(id);
+        if trade.limit.is_some() {
+            let chan = state.chan.clone();
+            tokio::spawn(async move {
+                match trade.swap_later(chan).await {
+                    Ok(trade) => info!("limit order {} done {:?} {:?}", trade.id, trade.state, trade.error),
+                    Err(e) => error!("limit order {id} {e}"),
+                }
+            });
+        } else {
+            state.chan.trade.try_send(InternalCommand::SwapRequest(trade)).unwrap();
+        }
+    }
+    Ok(Json(json!({"trade_id":ids[0],"trade_ids":ids})))
+}
+
+/// Adds a new wallet to the user, returns its pubkey
+async fn add_wallet(
+    TypedHeader(auth): TypedHeader<Authorization<Basic>>,
+    State(state): State<ServerState>,
+) -> Result<Json<Value>,HttpErrorResponse> {
+    let mut user = get_user_from_auth(auth,&state.user_db).await?;
+    let kp = Keypair::new();
+    user.wallets.push(kp.to_base58_string());
+    user.db_upsert_mongo(&state.user_db).await;
+    Ok(Json(json!({"wallet":kp.pubkey().to_string()})))
+}
+
+/// Every wallet of the user with its SOL balance and SOL in open trades
+async fn get_wallets(
+    TypedHeader(auth): TypedHeader<Authorization<Basic>>,
+    State(state): State<ServerState>,
+) -> Result<Json<Value>,HttpErrorResponse> {
+    let user = get_user_from_auth(auth,&state.user_db).await?;
+    let pubkeys = user.keypairs().iter().map(|x| x.pubkey()).collect::<Vec<_>>();
+    let active = wallet::open_trades(&state.trade_db, &state.chan, &pubkeys).await;
+    let mut wallets = vec![];
+    for kp in user.keypairs() {
+        let pubkey = kp.pubkey();
+        let lamports = wallet::balance(&pubkey).await.map_err(internal_error)?;
+        wallets.push(json!({
+            "wallet":pubkey.to_string(),
+            "sol":Decimal::from(lamports) / Decimal::from(LAMPORTS_PER_SOL),
+            "exposure":wallet::exposure(&active, &pubkey)
+        }));
+    }
+    Ok(Json(json!({"wallets":wallets})))
+}
+
+/// Moves SOL between two of the user's wallets
+async fn fund_wallet(
+    TypedHeader(auth): TypedHeader<Authorization<Basic>>,
+    State(state): State<ServerState>,
+    Json(payload): Json<FundPrompt>,
+) -> Result<Json<Value>,HttpErrorResponse> {
+    let user = get_user_from_auth(auth,&state.user_db).await?;
+    if payload.sol_ui <= Decimal::ZERO {
+        return Err((StatusCode::BAD_REQUEST,"sol_ui must be positive".to_string()));
+    }
+    let wallets = user.keypairs();
+    let find = |pubkey:&str| wallets
+        .iter()
+        .find(|x| x.pubkey().to_string() == pubkey)
+        .ok_or((StatusCode::BAD_REQUEST,format!("unknown wallet {pubkey}")));
+    let from = find(&payload.from)?;
+    let to = find(&payload.to)?.pubkey();
+    let lamports = wallet::fund(from, &to, payload.sol_ui).await.map_err(internal_error)?;
+    Ok(Json(json!({"lamports":lamports})))
+}
+
+/// Moves all SOL of the user's other wallets into one, wallets with open trades are left alone
+async fn sweep_wallets(
+    TypedHeader(auth): TypedHeader<Authorization<Basic>>,
+    State(state): State<ServerState>,
+    Json(payload): Json<SweepPrompt>,
+) -> Result<Json<Value>,HttpErrorResponse> {
+    let user = get_user_from_auth(auth,&state.user_db).await?;
+    let wallets = user.keypairs();
+    let to = wallets
+        .iter()
+        .find(|x| x.pubkey().to_string() == payload.to)
+        .ok_or((StatusCode::BAD_REQUEST,format!("unknown wallet {}",payload.to)))?
+        .pubkey();
+    let pubkeys = wallets.iter().map(|x| x.pubkey()).collect::<Vec<_>>();
+    let active = wallet::open_trades(&state.trade_db, &state.chan, &pubkeys).await;
+    // any open trade keeps its wallet, not only the ones with SOL at cost
+    let idle = wallets
+        .into_iter()
+        .filter(|x| !active.iter().any(|t| t.root_kp().pubkey() == x.pubkey()))
+        .collect::<Vec<_>>();
+    let swept = wallet::sweep(&idle, &to)
+        .await
+        .into_iter()
+        .map(|(pubkey, r)| match r {
+            Ok(lamports) => json!({"wallet":pubkey.to_string(),"lamports":lamports}),
+            Err(e) => json!({"wallet":pubkey.to_string(),"error":e.to_string()}),
+        })
+        .collect::<Vec<_>>();
+    Ok(Json(json!({"swept":swept})))
 }
 
 /// Returns 200 - private_key on signup
@@ -135,6 +286,7 @@ async fn login(
     match user {
         Ok(mut user) => {
             user.private_key.clear();
+            user.wallets.clear();
             Ok(Json(user))
         }
         Err(_) => {
@@ -145,6 +297,10 @@ async fn login(
                 username: auth.username().to_string(),
                 password: auth.password().to_string(),
                 admin: false,
+                strategy: None,
+                wallets: vec![],
+                wallet_selection: Default::default(),
+                wallet_turn: 0,
             };
             println!("{:?}",user);
             let user = user.insert(&state.user_db)
@@ -185,31 +341,66 @@ async fn get_trade(
     State(state): State<ServerState>,
     axum::extract::Path(id): axum::extract::Path<i64>,
 ) -> Result<Json<Value>,HttpErrorResponse> {
-    let kp = get_user_from_auth(auth,&state.user_db)
-        .await
-        .map(|x|Keypair::from_base58_string(&x.private_key))?;
-
-    let trade = Trade::db_get_by_id_and_kp_mongo(&state.trade_db,id,kp.to_bytes().to_vec())
-        .await
-        .ok_or(internal_error("unknown trade"))?;
-    if trade.state == TradeState::BuySuccess {
-        let sig = trade.signature_unchecked();
-        Ok(
-            Json(
-                json!({
-                    "signature":sig.to_string()
-                })
-            )
-        )
+    let trade = get_trade_from_auth(auth,&state,id).await?;
+    // a failed buy holds nothing, so it has no position signature
+    let sig = if trade.is_open() {
+        Some(trade.signature_unchecked().to_string())
     } else {
-        Ok(
-            Json(
-                json!({
-                    "signature":null
-                })
-            )
+        None
+    };
+    Ok(
+        Json(
+            json!({
+                "signature":sig,
+                "state":trade.state,
+                "error":trade.error,
+                "needs_attention":trade.needs_attention,
+                "state_history":trade.state_history
+            })
         )
+    )
+}
+
+async fn sell_trade(
+    TypedHeader(auth): TypedHeader<Authorization<Basic>>,
+    State(state): State<ServerState>,
+    axum::extract::Path(id): axum::extract::Path<i64>,
+    Json(payload): Json<SellPrompt>,
+) -> Result<Json<Value>,HttpErrorResponse> {
+    let trade = get_trade_from_auth(auth.clone(),&state,id).await?;
+    if payload.fraction <= Decimal::ZERO || payload.fraction > Decimal::ONE {
+        return Err((StatusCode::BAD_REQUEST,"fraction must be between 0 and 1".to_string()));
+    }
+    if let Err(e) = trade.clone().with_sell_fraction(payload.fraction) {
+        return Err((StatusCode::BAD_REQUEST,e.to_string()));
     }
+    if let Some(behind) = payload.behind {
+        let user = get_user_from_auth(auth,&state.user_db).await?;
+        let pubkeys = user.keypairs().iter().map(|x| x.pubkey()).collect::<Vec<_>>();
+        let pending = wallet::open_trades(&state.trade_db, &state.chan, &pubkeys)
+            .await
+            .into_iter()
+            .any(|x| x.id == behind && x.state == TradeState::PendingBuy);
+        if !pending {
+            return Err((StatusCode::BAD_REQUEST,format!("trade {behind} is not a pending buy")));
+        }
+    }
+    state.chan.trade.try_send(InternalCommand::ScaleOutRequest(trade.id, payload.fraction, payload.behind)).unwrap();
+    Ok(Json(json!({"trade_id":trade.id})))
+}
+
+async fn add_to_trade(
+    TypedHeader(auth): TypedHeader<Authorization<Basic>>,
+    State(state): State<ServerState>,
+    axum::extract::Path(id): axum::extract::Path<i64>,
+    Json(payload): Json<AddPrompt>,
+) -> Result<Json<Value>,HttpErrorResponse> {
+    let trade = get_trade_from_auth(auth,&state,id).await?;
+    if payload.sol_ui <= Decimal::ZERO {
+        return Err((StatusCode::BAD_REQUEST,"sol_ui must be positive".to_string()));
+    }
+    state.chan.trade.try_send(InternalCommand::ScaleInRequest(trade.id, payload.sol_ui)).unwrap();
+    Ok(Json(json!({"trade_id":trade.id})))
 }
 
 // Handler to delete a user by ID
@@ -293,4 +484,19 @@ async fn get_user_from_auth(
     }
 }
 
+async fn get_trade_from_auth(
+    auth: Authorization<Basic>,
+    state:&ServerState,
+    id: i64,
+) -> Result<Trade,HttpErrorResponse> {
+    let user = get_user_from_auth(auth,&state.user_db).await?;
+    // the trade may be signed by any of the user's wallets
+    for kp in user.keypairs() {
+        if let Some(trade) = Trade::db_get_by_id_and_kp_mongo(&state.trade_db,id,kp.to_bytes().to_vec()).await {
+            return Ok(trade);
+        }
+    }
+    Err((StatusCode::NOT_FOUND,"Trade not found".to_string()))
+}
+
 type HttpErrorResponse = (StatusCode,String);
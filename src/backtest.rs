@@ -0,0 +1,272 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Read, Write};
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use yellowstone_grpc_proto::geyser::SubscribeUpdate;
+use yellowstone_grpc_proto::prost::Message;
+use crate::strategy::*;
+use crate::trade::*;
+use crate::trade_cache::Cache;
+use crate::trade_chan::decode_tx;
+use crate::trade_cmd::InternalCommand;
+use crate::trade_rpc::paper;
+
+/// Appends raw geyser updates to a file, each one as
+/// `[unix millis i64][len u32][SubscribeUpdate protobuf]` little endian
+pub struct Recorder {
+    file: BufWriter<File>,
+}
+
+impl Recorder {
+    pub fn create(path: &str) -> anyhow::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Recorder {
+            file: BufWriter::new(file),
+        })
+    }
+    pub fn record(&mut self, update: &SubscribeUpdate) -> anyhow::Result<()> {
+        let bytes = update.encode_to_vec();
+        self.file.write_all(&Utc::now().timestamp_millis().to_le_bytes())?;
+        self.file.write_all(&(bytes.len() as u32).to_le_bytes())?;
+        self.file.write_all(&bytes)?;
+        Ok(())
+    }
+}
+
+/// Reads a file written by [Recorder]
+pub struct Replay {
+    file: BufReader<File>,
+}
+
+impl Replay {
+    pub fn open(path: &str) -> anyhow::Result<Self> {
+        Ok(Replay {
+            file: BufReader::new(File::open(path)?),
+        })
+    }
+    /// None at the end of the file, a record cut short by a crash counts as the end
+    pub fn next_update(&mut self) -> anyhow::Result<Option<(DateTime<Utc>, SubscribeUpdate)>> {
+        let mut time = [0u8; 8];
+        let mut len = [0u8; 4];
+        if self.file.read_exact(&mut time).is_err() || self.file.read_exact(&mut len).is_err() {
+            return Ok(None);
+        }
+        let mut bytes = vec![0u8; u32::from_le_bytes(len) as usize];
+        if self.file.read_exact(&mut bytes).is_err() {
+            return Ok(None);
+        }
+        let time = DateTime::from_timestamp_millis(i64::from_le_bytes(time)).unwrap_or_default();
+        Ok(Some((time, SubscribeUpdate::decode(bytes.as_slice())?)))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BacktestTrade {
+    pub id: i64,
+    pub mint: String,
+    pub state: TradeState,
+    pub buys: usize,
+    pub sells: usize,
+    /// realized plus unrealized pnl in SOL before fees
+    pub pnl: Decimal,
+    /// jito, priority and base fees in SOL
+    pub fees: Decimal,
+    pub net_pnl: Decimal,
+}
+
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct BacktestReport {
+    pub updates: usize,
+    pub trades: Vec<BacktestTrade>,
+    /// closed trades with positive net pnl
+    pub wins: usize,
+    pub losses: usize,
+    pub win_rate: Decimal,
+    pub net_pnl: Decimal,
+    pub fees: Decimal,
+    /// largest fall of cumulative net pnl from its peak, in SOL
+    pub max_drawdown: Decimal,
+}
+
+/// Runs recorded updates through `decode_tx` and the same fill transitions as trade_chan.
+/// Every swap is a paper swap, so fills come from the reserves of the next log.
+pub struct Backtest {
+    db: Cache,
+    strategies: Strategies,
+    /// trades in the order they closed
+    closed: Vec<Trade>,
+    updates: usize,
+}
+
+impl Backtest {
+    /// `trades` are watched from `TradeState::Buy`
+    pub fn new(trades: Vec<Trade>, strategies: Strategies) -> Self {
+        let mut db = Cache {
+            trades: Default::default(),
+            mints: Default::default(),
+            oneshots: Default::default(),
+            jito_tip: None,
+            sends_left: Default::default(),
+        };
+        for mut trade in trades {
+            trade.paper = true;
+            trade.state = TradeState::Buy;
+            db.upsert(&trade);
+        }
+        Backtest {
+            db,
+            strategies,
+            closed: vec![],
+            updates: 0,
+        }
+    }
+
+    /// `speed` is how many times faster than recorded, 0 replays without waiting
+    pub async fn run(mut self, replay: &mut Replay, speed: u32) -> anyhow::Result<BacktestReport> {
+        let mut last = None;
+        while let Some((time, update)) = replay.next_update()? {
+            if let Some(last) = last.filter(|_| speed > 0) {
+                if let Ok(wait) = (time - last).to_std() {
+                    tokio::time::sleep(wait / speed).await;
+                }
+            }
+            last = Some(time);
+            self.updates += 1;
+            for cmd in decode_tx(Ok(update), &self.db) {
+                self.on_command(cmd).await;
+            }
+        }
+        Ok(self.report())
+    }
+
+    async fn on_command(&mut self, cmd: InternalCommand) {
+        match cmd {
+            InternalCommand::PumpSwapMaybe { trade, interested_tx } => {
+                let Some(trade) = interested_tx.with_swap_pool(trade) else {
+                    return;
+                };
+                let trade = match trade {
+                    Ok(trade) => trade,
+                    Err(e) => {
+                        error!("{e}");
+                        return;
+                    }
+                };
+                if trade.is_admin() {
+                    return;
+                }
+                if trade.state == TradeState::Buy
+                    && self.strategies.on_log(&trade, &interested_tx, &self.db) == StrategyIntent::Buy
+                {
+                    self.swap(trade).await;
+                }
+            }
+            InternalCommand::TradeUpdate { mut trade, interested_tx } => {
+                let signature = interested_tx.signature;
+                let ours = trade.rpc_logs.iter().any(|x| x.signature() == signature);
+                match trade.state {
+                    TradeState::PendingBuy if ours => {
+                        let amount_out = trade.plog.amount_out;
+                        if let Err(e) = trade.apply_buy_fill(signature, amount_out) {
+                            error!("{e}");
+                            return;
+                        }
+                        self.db.upsert(&trade);
+                    }
+                    TradeState::BuySuccess | TradeState::PartiallyClosed => {
+                        trade.update_pct();
+                        trade.update_high_price();
+                        self.db.upsert(&trade);
+                        if let StrategyIntent::Sell(fraction) = self.strategies.on_log(&trade, &interested_tx, &self.db) {
+                            trade.sell_tp_level = trade.tp_level_reached();
+                            match trade.with_sell_fraction(fraction) {
+                                Ok(trade) => self.swap(trade).await,
+                                Err(e) => error!("{e}"),
+                            }
+                        }
+                    }
+                    TradeState::PendingSell if ours => {
+                        if let Err(e) = trade.apply_sell_fill(signature) {
+                            error!("{e}");
+                            return;
+                        }
+                        if trade.state == TradeState::SellSuccess {
+                            info!("{}", trade.console_log());
+                            self.db.remove(&trade);
+                            self.closed.push(trade);
+                        } else {
+                            self.db.upsert(&trade);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            InternalCommand::LimitExpired(trade) => {
+                self.db.remove(&trade);
+            }
+            _ => {}
+        }
+    }
+
+    /// Same as `trade_chan::swap` with a single paper rpc
+    async fn swap(&mut self, mut trade: Trade) {
+        let to = if trade.is_buy() {
+            TradeState::PendingBuy
+        } else {
+            TradeState::PendingSell
+        };
+        if let Err(e) = trade.transition(to, "paper swap") {
+            error!("{e}");
+            return;
+        }
+        match trade.rebuild_instructions().send(paper()).await {
+            Ok(trade) => self.db.upsert(&trade),
+            Err(trade) => error!("{:?}", trade.error),
+        }
+    }
+
+    fn report(&self) -> BacktestReport {
+        let mut report = BacktestReport {
+            updates: self.updates,
+            ..Default::default()
+        };
+        let mut peak = Decimal::ZERO;
+        let mut equity = Decimal::ZERO;
+        // open trades go last, marked at their last price
+        let open = self.db.values();
+        for trade in self.closed.iter().chain(open.iter()) {
+            let fees = trade.swap_fee_sol_ui() * Decimal::from(trade.fills.len());
+            let pnl = trade.realized_pnl() + trade.unrealized_pnl();
+            let net_pnl = pnl - fees;
+            if trade.state == TradeState::SellSuccess {
+                if net_pnl > Decimal::ZERO {
+                    report.wins += 1;
+                } else {
+                    report.losses += 1;
+                }
+            }
+            equity += net_pnl;
+            peak = peak.max(equity);
+            report.max_drawdown = report.max_drawdown.max(peak - equity);
+            report.net_pnl += net_pnl;
+            report.fees += fees;
+            report.trades.push(BacktestTrade {
+                id: trade.id,
+                mint: trade.mint().to_string(),
+                state: trade.state,
+                buys: trade.fills.iter().filter(|x| x.side == TradeFillSide::Buy).count(),
+                sells: trade.fills.iter().filter(|x| x.side == TradeFillSide::Sell).count(),
+                pnl,
+                fees,
+                net_pnl,
+            });
+        }
+        let closed = report.wins + report.losses;
+        if closed > 0 {
+            report.win_rate = Decimal::from(report.wins) / Decimal::from(closed);
+        }
+        report
+    }
+}
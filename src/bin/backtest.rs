@@ -0,0 +1,45 @@
+use rust_decimal::Decimal;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Keypair;
+use std::str::FromStr;
+use wolf_trader::backtest::{Backtest, Replay};
+use wolf_trader::strategy::Strategies;
+use wolf_trader::trade::*;
+
+/// backtest <recording> <speed> <sol> <tp> <sl> <mint>...
+#[tokio::main]
+async fn main() {
+    env_logger::Builder::from_env(env_logger::Env::new().default_filter_or("info"))
+        .format_timestamp_millis()
+        .init();
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.len() < 7 {
+        panic!("usage: backtest <recording> <speed> <sol> <tp> <sl> <mint>...");
+    }
+    let speed = args[2].parse::<u32>().unwrap();
+    let sol = Decimal::from_str(&args[3]).unwrap();
+    let tp = Decimal::from_str(&args[4]).unwrap();
+    let sl = Decimal::from_str(&args[5]).unwrap();
+
+    let kp = Keypair::new();
+    let trades = args[6..]
+        .iter()
+        .enumerate()
+        .map(|(i, mint)| {
+            let mut trade = Trade::dangerous_user(kp.to_bytes().to_vec());
+            trade.id = i as i64 + 1;
+            trade.amm = TradePool::PumpBondingCurveMint(Pubkey::from_str(mint).unwrap());
+            trade.cfg.max_sol = sol;
+            trade.cfg.tp = tp;
+            trade.cfg.sl = sl;
+            trade
+        })
+        .collect::<Vec<_>>();
+
+    let mut replay = Replay::open(&args[1]).unwrap();
+    let report = Backtest::new(trades, Strategies::default())
+        .run(&mut replay, speed)
+        .await
+        .unwrap();
+    println!("{}", serde_json::to_string_pretty(&report).unwrap());
+}
@@ -0,0 +1,63 @@
+use std::sync::RwLock;
+use std::time::Duration;
+use log::{error, info};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::hash::Hash;
+use crate::trade_rpc::solana_rpc_client;
+
+#[derive(Clone, Copy, Debug)]
+pub struct RecentBlockhash {
+    pub hash: Hash,
+    /// transactions with this hash can land up to this block height
+    pub last_valid_block_height: u64,
+}
+
+static BLOCKHASH: RwLock<Option<RecentBlockhash>> = RwLock::new(None);
+static BLOCK_HEIGHT: RwLock<u64> = RwLock::new(0);
+static EPOCH: RwLock<u64> = RwLock::new(0);
+
+/// Latest confirmed blockhash, none until `blockhash_chan` polled once
+pub fn recent_blockhash() -> Option<RecentBlockhash> {
+    *BLOCKHASH.read().unwrap()
+}
+
+/// Processed block height, 0 until `blockhash_chan` polled once
+pub fn block_height() -> u64 {
+    *BLOCK_HEIGHT.read().unwrap()
+}
+
+/// Current epoch, 0 until `blockhash_chan` polled once
+pub fn current_epoch() -> u64 {
+    *EPOCH.read().unwrap()
+}
+
+/// Keeps [recent_blockhash], [block_height] and [current_epoch] fresh so building a transaction needs no rpc call
+pub async fn blockhash_chan() {
+    let rpc = solana_rpc_client();
+    info!("tracking blockhash");
+    let mut polls = 0u64;
+    loop {
+        match rpc.get_latest_blockhash_with_commitment(CommitmentConfig::confirmed()).await {
+            Ok((hash, last_valid_block_height)) => {
+                *BLOCKHASH.write().unwrap() = Some(RecentBlockhash {
+                    hash,
+                    last_valid_block_height,
+                });
+            }
+            Err(e) => error!("blockhash {e}"),
+        }
+        match rpc.get_block_height().await {
+            Ok(height) => *BLOCK_HEIGHT.write().unwrap() = height,
+            Err(e) => error!("block height {e}"),
+        }
+        // an epoch lasts days, once a minute is plenty
+        if polls % 60 == 0 {
+            match rpc.get_epoch_info().await {
+                Ok(info) => *EPOCH.write().unwrap() = info.epoch,
+                Err(e) => error!("epoch {e}"),
+            }
+        }
+        polls += 1;
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
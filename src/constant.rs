@@ -52,6 +52,16 @@ pub const JITO_TIPS:[&str;8] = [
 pub const PUMP_SWAP_CODE:[u8;8] = [189, 219, 127, 211, 78, 230, 97, 238];
 pub const PUMP_BUY_CODE:[u8;8] = [102, 6, 61, 18, 1, 218, 235, 234];
 pub const PUMP_SELL_CODE:[u8;8] = [51, 230, 133, 164, 1, 127, 131, 173];
+pub const PUMP_CREATE_CODE:[u8;8] = [27, 114, 169, 77, 222, 235, 99, 118];
+pub const PUMP_COMPLETE_CODE:[u8;8] = [95, 114, 97, 156, 212, 46, 152, 8];
+/// anchor `SwapEvent`, emitted by both CP-Swap and CLMM
+pub const RAYDIUM_SWAP_EVENT_CODE:[u8;8] = [64, 198, 205, 232, 38, 8, 113, 226];
+pub const RAYDIUM_CP_SWAP_BASE_INPUT_CODE:[u8;8] = [143, 190, 90, 218, 196, 30, 51, 222];
+pub const RAYDIUM_CP_SWAP_BASE_OUTPUT_CODE:[u8;8] = [55, 217, 98, 86, 163, 74, 180, 173];
+pub const RAYDIUM_CLMM_SWAP_V2_CODE:[u8;8] = [43, 4, 237, 11, 26, 201, 30, 98];
+/// PumpSwap `buy` and `sell` take the curve's instruction codes, these are their events
+pub const PUMP_AMM_BUY_EVENT_CODE:[u8;8] = [103, 244, 82, 31, 44, 245, 119, 119];
+pub const PUMP_AMM_SELL_EVENT_CODE:[u8;8] = [62, 47, 55, 10, 165, 3, 220, 42];
 
 pub const SOLANA_MINT_STR: &str = "So11111111111111111111111111111111111111112";
 pub const SOLANA_MINT: Pubkey = Pubkey::from_str_const("So11111111111111111111111111111111111111112");
@@ -61,7 +71,23 @@ pub const PUMP_PROGRAM:Pubkey = Pubkey::from_str_const("6EF8rrecthR5Dkzon8Nwu78h
 pub const PUMP_GLOBAL:Pubkey = Pubkey::from_str_const("4wTV1YmiEkRvAtNtsSGPtUrqRYQMe5SKy2uB4Jjaxnjf");
 pub const PUMP_EVENT_AUTHORITY:Pubkey = Pubkey::from_str_const("Ce6TQqeHC9p8KetsN6JsjHK7UTZk7nasjjnr7XxXp9F1");
 pub const PUMP_FEE:Pubkey = Pubkey::from_str_const("CebN5WGQ4jvEPvsVU4EoHEpgzq1VV7AbicfhtW4xC9iM");
-
+pub const PUMP_FEE_BPS:u64 = 100;
+pub const PUMP_AMM_PROGRAM:Pubkey = Pubkey::from_str_const("pAMMBay6oceH9fJKBRHGP5D4bD4sWpmSwMn52FMfXEA");
+pub const PUMP_AMM_GLOBAL_CONFIG:Pubkey = Pubkey::from_str_const("ADyA8hdefvWN2dbGGWFotbzWxrAvLW83WG6QCVXvJKqw");
+pub const PUMP_AMM_EVENT_AUTHORITY:Pubkey = Pubkey::from_str_const("GS4CU59F31iL7aR2Q8zVS8DRrcRnXX1yjQ66TqNVQnaR");
+pub const PUMP_AMM_GLOBAL_VOLUME_ACCUMULATOR:Pubkey = Pubkey::from_str_const("C2aFPdENg4A2HQsmrd5rTw5TaYBX5Ku887cWjbFKtZpw");
+pub const PUMP_FEE_PROGRAM:Pubkey = Pubkey::from_str_const("pfeeUxB6jkeY1Hxd7CsFCAjcbHA9rWtchMGdZ6VojVZ");
+/// PumpSwap's fee config in the fee program
+pub const PUMP_AMM_FEE_CONFIG:Pubkey = Pubkey::from_str_const("5PHirr8joyTMp9JMm6nW7hNDVyEYdkzDqazxPD7RaTjx");
+/// PumpSwap lp and protocol fee until a swap event tells the pool's
+pub const PUMP_AMM_FEE_BPS:u64 = 25;
+pub const RAY_AMM4_FEE_BPS:u64 = 25;
+/// CP-Swap and CLMM fee in millionths until the pool's config is loaded, the most common tier
+pub const RAY_DEFAULT_FEE_RATE:u64 = 2_500;
+
+
+/// raw geyser updates are appended here when set, see `backtest::Recorder`
+pub const GEYSER_RECORD_PATH: Option<&str> = option_env!("GEYSER_RECORD_PATH");
 
 pub const RPC1: &str = env!("RPC1");
 pub const RPC1_NAME: &str = env!("RPC1_NAME");
@@ -84,6 +110,11 @@ pub const JITO2_LOCATION: &str = env!("JITO2_LOCATION");
 pub const GRPC1: &str = env!("GRPC1");
 pub const GRPC1_NAME: &str = env!("GRPC1_NAME");
 pub const GRPC_LOCATION: &str = env!("GRPC1_LOCATION");
+/// extra geyser endpoints raced against GRPC1
+pub const GRPC2: Option<&str> = option_env!("GRPC2");
+pub const GRPC2_NAME: &str = match option_env!("GRPC2_NAME") { Some(x) => x, None => "GRPC2" };
+pub const GRPC3: Option<&str> = option_env!("GRPC3");
+pub const GRPC3_NAME: &str = match option_env!("GRPC3_NAME") { Some(x) => x, None => "GRPC3" };
 
 pub const KAFKA_URL: &str = env!("KAFKA_URL");
 pub const PG_URL: &str = env!("PG_URL");
@@ -91,6 +122,9 @@ pub const REDIS_URL: &str = env!("REDIS_URL");
 
 pub const RAYDIUM_V4_PROGRAM:Pubkey = Pubkey::from_str_const("675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8");
 pub const RAYDIUM_V4_AUTHORITY:Pubkey = Pubkey::from_str_const("5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1");
+pub const RAYDIUM_CP_PROGRAM:Pubkey = Pubkey::from_str_const("CPMMoo8L3F4NbTegBCKVNunggL7H1ZpdTHKxQB5qKP1C");
+pub const RAYDIUM_CLMM_PROGRAM:Pubkey = Pubkey::from_str_const("CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK");
+pub const SOLANA_MEMO_PROGRAM:Pubkey = Pubkey::from_str_const("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr");
 pub const SOLANA_SYSTEM_PROGRAM:Pubkey = Pubkey::from_str_const("11111111111111111111111111111111");
 pub const SOLANA_RENT_PROGRAM:Pubkey = Pubkey::from_str_const("SysvarRent111111111111111111111111111111111");
 pub const SOLANA_SERUM_PROGRAM:Pubkey = Pubkey::from_str_const("srmqPvymJeFKQ4zGQed1GFppgkRHL9kaELCbyksJtPX");
@@ -167,7 +201,23 @@ pub fn get_keypair() -> &'static Keypair {
 }
 
 pub fn geyser() -> yellowstone_grpc_client::GeyserGrpcBuilder {
-    yellowstone_grpc_client::GeyserGrpcClient::build_from_static(GRPC1)
+    geyser_at(GRPC1)
+}
+
+pub fn geyser_at(endpoint: &'static str) -> yellowstone_grpc_client::GeyserGrpcBuilder {
+    yellowstone_grpc_client::GeyserGrpcClient::build_from_static(endpoint)
+}
+
+/// (name, endpoint) of every configured geyser
+pub fn geyser_endpoints() -> Vec<(&'static str, &'static str)> {
+    let mut endpoints = vec![(GRPC1_NAME, GRPC1)];
+    if let Some(x) = GRPC2 {
+        endpoints.push((GRPC2_NAME, x));
+    }
+    if let Some(x) = GRPC3 {
+        endpoints.push((GRPC3_NAME, x));
+    }
+    endpoints
 }
 
 
@@ -0,0 +1,128 @@
+use std::collections::{HashSet, VecDeque};
+use std::time::Duration;
+use futures::{SinkExt, StreamExt};
+use log::{error, info, warn};
+use tokio::sync::{mpsc, watch};
+use yellowstone_grpc_proto::geyser::subscribe_update::UpdateOneof;
+use yellowstone_grpc_proto::geyser::{SubscribeRequest, SubscribeUpdate, SubscribeUpdateTransaction};
+use crate::constant::*;
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// signatures remembered for de-duplication
+const SEEN_LEN: usize = 50_000;
+
+/// Subscribes to every endpoint in `geyser_endpoints` with the latest request and
+/// forwards the first copy of each transaction. A new request on `request` replaces the
+/// subscription in place. Endpoints reconnect on their own with backoff, so the stream
+/// only ends when the receiver is dropped.
+pub fn geyser_stream(request: watch::Receiver<SubscribeRequest>) -> mpsc::Receiver<SubscribeUpdate> {
+    let (merge_tx, mut merge_rx) = mpsc::channel::<SubscribeUpdate>(10_000);
+    for (name, endpoint) in geyser_endpoints() {
+        tokio::spawn(subscribe_forever(name, endpoint, request.clone(), merge_tx.clone()));
+    }
+
+    let (tx, rx) = mpsc::channel(10_000);
+    tokio::spawn(async move {
+        let mut seen = Seen::default();
+        while let Some(update) = merge_rx.recv().await {
+            if let Some(signature) = signature(&update) {
+                if !seen.insert(signature) {
+                    continue;
+                }
+            }
+            if tx.send(update).await.is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+async fn subscribe_forever(
+    name: &'static str,
+    endpoint: &'static str,
+    mut request: watch::Receiver<SubscribeRequest>,
+    tx: mpsc::Sender<SubscribeUpdate>,
+) {
+    let mut backoff = MIN_BACKOFF;
+    loop {
+        match geyser_at(endpoint).connect().await {
+            Ok(mut client) => match client.subscribe_with_request(Some(request.borrow_and_update().clone())).await {
+                // keep the sink alive, dropping it closes the subscription
+                Ok((mut subscribe_tx, mut stream)) => {
+                    info!("connected to geyser {name}");
+                    backoff = MIN_BACKOFF;
+                    loop {
+                        let message = tokio::select! {
+                            message = stream.next() => message,
+                            changed = request.changed() => {
+                                // the engine is gone
+                                if changed.is_err() {
+                                    return;
+                                }
+                                let next = request.borrow_and_update().clone();
+                                if let Err(e) = subscribe_tx.send(next).await {
+                                    error!("geyser {name} resubscribe {e}");
+                                    break;
+                                }
+                                continue;
+                            }
+                        };
+                        match message {
+                            Some(Ok(update)) => {
+                                if tx.send(update).await.is_err() {
+                                    return;
+                                }
+                            }
+                            Some(Err(e)) => {
+                                error!("geyser {name} {e}");
+                                break;
+                            }
+                            None => break,
+                        }
+                    }
+                    warn!("geyser {name} stream ended");
+                }
+                Err(e) => error!("geyser {name} subscribe {e}"),
+            },
+            Err(e) => error!("geyser {name} connect {e}"),
+        }
+        info!("reconnecting to geyser {name} in {backoff:?}");
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+fn signature(update: &SubscribeUpdate) -> Option<Vec<u8>> {
+    match &update.update_oneof {
+        Some(UpdateOneof::Transaction(SubscribeUpdateTransaction {
+            transaction: Some(info),
+            ..
+        })) => Some(info.signature.clone()),
+        _ => None,
+    }
+}
+
+/// Bounded set of recent signatures, oldest are forgotten first
+#[derive(Default)]
+struct Seen {
+    set: HashSet<Vec<u8>>,
+    order: VecDeque<Vec<u8>>,
+}
+
+impl Seen {
+    /// False when the signature was already seen
+    fn insert(&mut self, signature: Vec<u8>) -> bool {
+        if !self.set.insert(signature.clone()) {
+            return false;
+        }
+        self.order.push_back(signature);
+        if self.order.len() > SEEN_LEN {
+            if let Some(old) = self.order.pop_front() {
+                self.set.remove(&old);
+            }
+        }
+        true
+    }
+}
@@ -1,40 +1,45 @@
 use crate::net::connect_tls;
-use chrono::{DateTime, Utc};
 use fastwebsockets::OpCode;
 use log::{error, info};
-use rust_decimal::Decimal;
-use serde::{Deserialize, Serialize};
+use std::time::Duration;
 use crate::chan::Chan;
-use crate::cmd::InternalCommand;
-
-#[derive(Debug, Serialize, Deserialize,Clone,Copy)]
-pub struct TipStatistics {
-    pub time: DateTime<Utc>,
-    pub landed_tips_25th_percentile: Decimal,
-    pub landed_tips_50th_percentile: Decimal,
-    pub landed_tips_75th_percentile: Decimal,
-    pub landed_tips_95th_percentile: Decimal,
-    pub landed_tips_99th_percentile: Decimal,
-    pub ema_landed_tips_50th_percentile: Decimal,
-}
+use crate::trade::TipStatistics;
+use crate::trade_cmd::InternalCommand;
 
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
 
+/// Streams landed tip stats into trade_chan, reconnecting when the socket drops
 pub async fn jito_chan(chan:Chan){
     let domain = "https://bundles.jito.wtf/api/v1/bundles/tip_stream";
-    let mut ws = connect_tls(domain).await.unwrap();
-    info!("connected to websocket server");
-    while let Ok(frame) = ws.read_frame().await {
-
-        match frame.opcode {
-            OpCode::Text => {
-                let tips  = serde_json::from_slice::<Vec<TipStatistics>>(frame.payload.as_ref()).unwrap();
-                if let Some(tip) = tips.get(0) {
-                    // chan.trade.try_send(InternalCommand::JitoTip(tip.clone())).unwrap();
+    let mut backoff = Duration::from_secs(1);
+    loop {
+        match connect_tls(domain).await {
+            Ok(mut ws) => {
+                info!("connected to jito tip stream");
+                backoff = Duration::from_secs(1);
+                while let Ok(frame) = ws.read_frame().await {
+                    match frame.opcode {
+                        OpCode::Text => {
+                            match serde_json::from_slice::<Vec<TipStatistics>>(frame.payload.as_ref()) {
+                                Ok(tips) => {
+                                    if let Some(tip) = tips.get(0) {
+                                        // newer stats replace older, dropping one when busy is fine
+                                        let _ = chan.trade.try_send(InternalCommand::JitoTip(tip.clone()));
+                                    }
+                                }
+                                Err(e) => error!("tip stream {e}"),
+                            }
+                        }
+                        OpCode::Close => break,
+                        _ => {
+                            error!("received non-text opcode {:?}", frame.opcode);
+                        }
+                    }
                 }
             }
-            _ => {
-                error!("received non-text opcode {:?}", frame.opcode);
-            }
+            Err(e) => error!("tip stream connect {e}"),
         }
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
     }
-}
\ No newline at end of file
+}
@@ -14,8 +14,8 @@ pub mod trade_rpc;
 pub mod trade_chan;
 // pub mod program_log;
 // pub mod websocket_server;
-// pub mod jito_chan;
-// pub mod net;
+pub mod jito_chan;
+pub mod net;
 pub mod solana;
 pub mod pump;
 // pub mod trade;
@@ -35,4 +35,14 @@ pub mod swap_config;
 pub mod plog;
 pub mod api;
 pub mod user;
+pub mod strategy;
+pub mod backtest;
+pub mod geyser;
+pub mod blockhash;
+pub mod lookup_table;
+pub mod nonce;
+pub mod wallet;
+pub mod raydium;
+pub mod pump_amm;
+pub mod quote;
 // pub mod user;
@@ -0,0 +1,138 @@
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::RwLock;
+use log::{error, info};
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_sdk::address_lookup_table::instruction::{create_lookup_table, extend_lookup_table};
+use solana_sdk::address_lookup_table::state::AddressLookupTable;
+use solana_sdk::address_lookup_table::AddressLookupTableAccount;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Keypair;
+use solana_sdk::signer::Signer;
+use solana_sdk::transaction::Transaction;
+use crate::constant::*;
+use crate::raydium::ray_cp_authority;
+use crate::trade_rpc::solana_rpc_client;
+
+/// offset of the authority in a lookup table account, after the meta type, deactivation slot,
+/// last extended slot, its start index and the option tag
+const AUTHORITY_OFFSET: usize = 22;
+
+/// Ready lookup tables by wallet
+static TABLES: RwLock<Option<HashMap<Pubkey, AddressLookupTableAccount>>> = RwLock::new(None);
+/// Wallets with a table being created or extended
+static PENDING: RwLock<Option<HashSet<Pubkey>>> = RwLock::new(None);
+
+/// Program and fee accounts every swap touches
+pub fn static_accounts() -> Vec<Pubkey> {
+    let mut accounts = vec![
+        PUMP_PROGRAM,
+        PUMP_GLOBAL,
+        PUMP_FEE,
+        PUMP_EVENT_AUTHORITY,
+        PUMP_AMM_PROGRAM,
+        PUMP_AMM_GLOBAL_CONFIG,
+        PUMP_AMM_EVENT_AUTHORITY,
+        PUMP_AMM_GLOBAL_VOLUME_ACCUMULATOR,
+        PUMP_AMM_FEE_CONFIG,
+        PUMP_FEE_PROGRAM,
+        RAYDIUM_V4_PROGRAM,
+        RAYDIUM_V4_AUTHORITY,
+        RAYDIUM_CP_PROGRAM,
+        ray_cp_authority(),
+        RAYDIUM_CLMM_PROGRAM,
+        SOLANA_MEMO_PROGRAM,
+        SOLANA_SYSTEM_PROGRAM,
+        SOLANA_RENT_PROGRAM,
+        SOLANA_ATA_PROGRAM,
+        SOLANA_MINT,
+        spl_token::id(),
+        spl_token_2022::id(),
+        solana_sdk::compute_budget::id(),
+    ];
+    accounts.extend(JITO_TIPS.iter().map(|x| Pubkey::from_str(x).unwrap()));
+    accounts
+}
+
+/// The wallet's table once it holds every static account, a missing one is set up in the background
+/// and swaps go out without it meanwhile
+pub fn lookup_table(wallet: &Keypair) -> Option<AddressLookupTableAccount> {
+    let pubkey = wallet.pubkey();
+    if let Some(table) = TABLES.read().unwrap().as_ref().and_then(|x| x.get(&pubkey)) {
+        return Some(table.clone());
+    }
+    let started = PENDING.write().unwrap().get_or_insert_with(HashSet::new).insert(pubkey);
+    if started {
+        let wallet = wallet.insecure_clone();
+        tokio::spawn(async move {
+            match ensure_lookup_table(&wallet).await {
+                Ok(table) => {
+                    info!("lookup table {} ready for {pubkey}", table.key);
+                    TABLES.write().unwrap().get_or_insert_with(HashMap::new).insert(pubkey, table);
+                }
+                Err(e) => error!("lookup table for {pubkey} {e}"),
+            }
+            PENDING.write().unwrap().get_or_insert_with(HashSet::new).remove(&pubkey);
+        });
+    }
+    None
+}
+
+/// Finds the table the wallet owns or creates one, then adds any missing static account.
+/// New entries can only be used from the next slot, so this waits for one confirmation.
+pub async fn ensure_lookup_table(wallet: &Keypair) -> anyhow::Result<AddressLookupTableAccount> {
+    let rpc = solana_rpc_client();
+    let authority = wallet.pubkey();
+    let owned = rpc
+        .get_program_accounts_with_config(
+            &solana_sdk::address_lookup_table::program::id(),
+            RpcProgramAccountsConfig {
+                filters: Some(vec![RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+                    AUTHORITY_OFFSET,
+                    authority.as_ref(),
+                ))]),
+                account_config: RpcAccountInfoConfig {
+                    commitment: Some(CommitmentConfig::confirmed()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )
+        .await?;
+
+    let (key, mut addresses) = match owned.first() {
+        Some((key, account)) => {
+            let table = AddressLookupTable::deserialize(&account.data)?;
+            (*key, table.addresses.to_vec())
+        }
+        None => {
+            let slot = rpc.get_slot_with_commitment(CommitmentConfig::finalized()).await?;
+            let (ix, key) = create_lookup_table(authority, authority, slot);
+            send_and_confirm(wallet, &[ix]).await?;
+            info!("created lookup table {key} for {authority}");
+            (key, vec![])
+        }
+    };
+
+    let missing = static_accounts()
+        .into_iter()
+        .filter(|x| !addresses.contains(x))
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        let ix = extend_lookup_table(key, authority, Some(authority), missing.clone());
+        send_and_confirm(wallet, &[ix]).await?;
+        addresses.extend(missing);
+    }
+    Ok(AddressLookupTableAccount { key, addresses })
+}
+
+/// Setup transactions for wallet accounts, not used for swaps
+pub async fn send_and_confirm(wallet: &Keypair, ix: &[solana_sdk::instruction::Instruction]) -> anyhow::Result<()> {
+    let rpc = solana_rpc_client();
+    let hash = rpc.get_latest_blockhash().await?;
+    let tx = Transaction::new_signed_with_payer(ix, Some(&wallet.pubkey()), &[wallet], hash);
+    rpc.send_and_confirm_transaction(&tx).await?;
+    Ok(())
+}
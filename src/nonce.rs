@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+use log::info;
+use solana_client::nonce_utils;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::hash::Hash;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Keypair;
+use solana_sdk::signer::Signer;
+use solana_sdk::system_instruction::create_nonce_account_with_seed;
+use crate::lookup_table::send_and_confirm;
+use crate::trade_rpc::solana_rpc_client;
+
+const NONCE_SEED: &str = "wolf-trader-nonce";
+
+/// Last nonce value read by wallet
+static NONCES: RwLock<Option<HashMap<Pubkey, Hash>>> = RwLock::new(None);
+
+/// The wallet's durable nonce account, derived so it needs no keypair of its own
+pub fn nonce_account(wallet: &Pubkey) -> Pubkey {
+    Pubkey::create_with_seed(wallet, NONCE_SEED, &solana_sdk::system_program::id()).unwrap()
+}
+
+/// Nonce value from the last [durable_nonce] call, a presigned transaction with any other value can't land
+pub fn cached_nonce(wallet: &Pubkey) -> Option<Hash> {
+    NONCES.read().unwrap().as_ref().and_then(|x| x.get(wallet).copied())
+}
+
+/// Reads the wallet's current nonce, creating the account on first use
+pub async fn durable_nonce(wallet: &Keypair) -> anyhow::Result<(Pubkey, Hash)> {
+    let rpc = solana_rpc_client();
+    let authority = wallet.pubkey();
+    let account = nonce_account(&authority);
+    // processed, a nonce advanced by a swap we just saw must not be signed against
+    let commitment = CommitmentConfig::processed();
+    let data = match rpc.get_account_with_commitment(&account, commitment).await?.value {
+        Some(data) => data,
+        None => {
+            let lamports = rpc
+                .get_minimum_balance_for_rent_exemption(solana_sdk::nonce::State::size())
+                .await?;
+            let ix = create_nonce_account_with_seed(&authority, &account, &authority, NONCE_SEED, &authority, lamports);
+            send_and_confirm(wallet, &ix).await?;
+            info!("created nonce account {account} for {authority}");
+            rpc.get_account_with_commitment(&account, commitment)
+                .await?
+                .value
+                .ok_or_else(|| anyhow::anyhow!("nonce account {account} missing after create"))?
+        }
+    };
+    let hash = nonce_utils::data_from_account(&data)?.blockhash();
+    NONCES.write().unwrap().get_or_insert_with(HashMap::new).insert(authority, hash);
+    Ok((account, hash))
+}
@@ -7,7 +7,13 @@ use rust_decimal::Decimal;
 use solana_sdk::pubkey::Pubkey;
 use raydium_amm::log::{InitLog, LogType, SwapBaseInLog, SwapBaseOutLog, WithdrawLog};
 use raydium_amm::math::SwapDirection;
-use crate::constant::PUMP_SWAP_CODE;
+use crate::constant::{PUMP_AMM_BUY_EVENT_CODE, PUMP_AMM_SELL_EVENT_CODE, PUMP_COMPLETE_CODE, PUMP_CREATE_CODE, PUMP_SWAP_CODE, RAYDIUM_SWAP_EVENT_CODE};
+
+/// CLMM and CP-Swap share the event name, the CLMM one is this long with its discriminator
+const CLMM_SWAP_EVENT_LEN: usize = 205;
+/// PumpSwap buy and sell events up to the creator fee, without the discriminator.
+/// Events from before the creator fee are padded with zeroes.
+const PUMP_AMM_EVENT_LEN: usize = 352;
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct ProgramLog {
@@ -26,6 +32,12 @@ pub enum ProgramLogInfo {
     RayWithdraw(WithdrawLog),
     RayInitLog(InitLog),
     PumpTradeLog(PumpTradeLog),
+    PumpCreateLog(PumpCreateLog),
+    PumpCompleteLog(PumpCompleteLog),
+    RayCpSwap(RayCpSwapLog),
+    RayClmmSwap(RayClmmSwapLog),
+    PumpAmmBuy(PumpAmmBuyLog),
+    PumpAmmSell(PumpAmmSellLog),
     #[default]
     Empty
 }
@@ -42,6 +54,133 @@ pub struct PumpTradeLog {
     pub virtual_token_reserves:u64,
 }
 
+/// A new coin launched on the curve
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PumpCreateLog {
+    pub name:String,
+    pub symbol:String,
+    pub uri:String,
+    pub mint:Pubkey,
+    pub bonding_curve:Pubkey,
+    /// wallet that created the coin
+    pub creator:Pubkey,
+}
+
+/// The curve sold out, the coin can't be traded on it anymore and migrates
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PumpCompleteLog {
+    pub user:Pubkey,
+    pub mint:Pubkey,
+    pub bonding_curve:Pubkey,
+    pub timestamp:i64,
+}
+
+/// Raydium CP-Swap swap, vault balances are from before the swap
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RayCpSwapLog {
+    pub pool_id:Pubkey,
+    pub input_vault_before:u64,
+    pub output_vault_before:u64,
+    pub input_amount:u64,
+    pub output_amount:u64,
+    pub input_transfer_fee:u64,
+    pub output_transfer_fee:u64,
+    pub base_input:bool,
+    pub input_mint:Pubkey,
+    pub output_mint:Pubkey,
+}
+
+/// Raydium CLMM swap, price and liquidity are from after the swap
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RayClmmSwapLog {
+    pub pool_state:Pubkey,
+    pub sender:Pubkey,
+    pub token_account_0:Pubkey,
+    pub token_account_1:Pubkey,
+    pub amount_0:u64,
+    pub transfer_fee_0:u64,
+    pub amount_1:u64,
+    pub transfer_fee_1:u64,
+    pub zero_for_one:bool,
+    pub sqrt_price_x64:u128,
+    pub liquidity:u128,
+    pub tick:i32,
+}
+
+/// PumpSwap buy, pool reserves are from before the swap
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PumpAmmBuyLog {
+    pub timestamp:i64,
+    pub base_amount_out:u64,
+    pub max_quote_amount_in:u64,
+    pub user_base_token_reserves:u64,
+    pub user_quote_token_reserves:u64,
+    pub pool_base_token_reserves:u64,
+    pub pool_quote_token_reserves:u64,
+    pub quote_amount_in:u64,
+    pub lp_fee_basis_points:u64,
+    pub lp_fee:u64,
+    pub protocol_fee_basis_points:u64,
+    pub protocol_fee:u64,
+    pub quote_amount_in_with_lp_fee:u64,
+    pub user_quote_amount_in:u64,
+    pub pool:Pubkey,
+    pub user:Pubkey,
+    pub user_base_token_account:Pubkey,
+    pub user_quote_token_account:Pubkey,
+    pub protocol_fee_recipient:Pubkey,
+    pub protocol_fee_recipient_token_account:Pubkey,
+    pub coin_creator:Pubkey,
+    pub coin_creator_fee_basis_points:u64,
+    pub coin_creator_fee:u64,
+}
+
+/// PumpSwap sell, pool reserves are from before the swap
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PumpAmmSellLog {
+    pub timestamp:i64,
+    pub base_amount_in:u64,
+    pub min_quote_amount_out:u64,
+    pub user_base_token_reserves:u64,
+    pub user_quote_token_reserves:u64,
+    pub pool_base_token_reserves:u64,
+    pub pool_quote_token_reserves:u64,
+    pub quote_amount_out:u64,
+    pub lp_fee_basis_points:u64,
+    pub lp_fee:u64,
+    pub protocol_fee_basis_points:u64,
+    pub protocol_fee:u64,
+    pub quote_amount_out_without_lp_fee:u64,
+    pub user_quote_amount_out:u64,
+    pub pool:Pubkey,
+    pub user:Pubkey,
+    pub user_base_token_account:Pubkey,
+    pub user_quote_token_account:Pubkey,
+    pub protocol_fee_recipient:Pubkey,
+    pub protocol_fee_recipient_token_account:Pubkey,
+    pub coin_creator:Pubkey,
+    pub coin_creator_fee_basis_points:u64,
+    pub coin_creator_fee:u64,
+}
+
+impl RayClmmSwapLog {
+    /// Constant product reserves that give the same price and depth inside the current tick,
+    /// token 0 then token 1
+    pub fn virtual_reserves(&self) -> (u64, u64) {
+        let sqrt_price = self.sqrt_price_x64 as f64 / 2f64.powi(64);
+        let liquidity = self.liquidity as f64;
+        ((liquidity / sqrt_price) as u64, (liquidity * sqrt_price) as u64)
+    }
+}
+
+/// Borsh string, u32 length then utf8
+fn read_string(bytes: &[u8], offset: &mut usize) -> Option<String> {
+    let len = u32::from_le_bytes(bytes.get(*offset..*offset + 4)?.try_into().ok()?) as usize;
+    let s = std::str::from_utf8(bytes.get(*offset + 4..*offset + 4 + len)?).ok()?;
+    *offset += 4 + len;
+    Some(s.to_string())
+}
+
 
 
 impl ProgramLog {
@@ -135,8 +274,32 @@ impl ProgramLog {
         swap_info
     }
     fn from_pump(bytes: &[u8]) -> Option<Self> {
-        let code = &bytes[..8];
-        if code == PUMP_SWAP_CODE.as_slice() {
+        let code = bytes.get(..8)?;
+        if code == PUMP_CREATE_CODE.as_slice() {
+            let mut offset = 8;
+            let name = read_string(bytes, &mut offset)?;
+            let symbol = read_string(bytes, &mut offset)?;
+            let uri = read_string(bytes, &mut offset)?;
+            let (mint, bonding_curve, creator) =
+                bincode::deserialize::<(Pubkey, Pubkey, Pubkey)>(bytes.get(offset..)?).ok()?;
+            Some(ProgramLog {
+                log: ProgramLogInfo::PumpCreateLog(PumpCreateLog {
+                    name,
+                    symbol,
+                    uri,
+                    mint,
+                    bonding_curve,
+                    creator,
+                }),
+                ..Default::default()
+            })
+        } else if code == PUMP_COMPLETE_CODE.as_slice() {
+            let log = bincode::deserialize::<PumpCompleteLog>(&bytes[8..]).ok()?;
+            Some(ProgramLog {
+                log: ProgramLogInfo::PumpCompleteLog(log),
+                ..Default::default()
+            })
+        } else if code == PUMP_SWAP_CODE.as_slice() {
             let log = bincode::deserialize::<PumpTradeLog>(&bytes[8..]).unwrap();
             Some(ProgramLog {
                 next_pc:log.virtual_sol_reserves,
@@ -150,6 +313,90 @@ impl ProgramLog {
             None
         }
     }
+    /// Raydium CP-Swap and CLMM swap events. Both pools sort their mints, token 0 is the coin side.
+    fn from_ray_pool(bytes: &[u8]) -> Option<Self> {
+        if bytes.get(..8)? != RAYDIUM_SWAP_EVENT_CODE.as_slice() {
+            return None;
+        }
+        if bytes.len() == CLMM_SWAP_EVENT_LEN {
+            let log = bincode::deserialize::<RayClmmSwapLog>(&bytes[8..]).ok()?;
+            let (coin, pc) = log.virtual_reserves();
+            Some(ProgramLog {
+                next_pc: pc,
+                next_coin: coin,
+                amount_out: if log.zero_for_one { log.amount_1 } else { log.amount_0 },
+                pc: 0,
+                coin: 0,
+                log: ProgramLogInfo::RayClmmSwap(log),
+            })
+        } else {
+            let log = bincode::deserialize::<RayCpSwapLog>(&bytes[8..]).ok()?;
+            let input_after = (log.input_vault_before + log.input_amount).saturating_sub(log.input_transfer_fee);
+            let output_after = log.output_vault_before.saturating_sub(log.output_amount);
+            let (coin, pc, next_coin, next_pc) = if log.input_mint < log.output_mint {
+                (log.input_vault_before, log.output_vault_before, input_after, output_after)
+            } else {
+                (log.output_vault_before, log.input_vault_before, output_after, input_after)
+            };
+            Some(ProgramLog {
+                next_pc,
+                next_coin,
+                amount_out: log.output_amount.saturating_sub(log.output_transfer_fee),
+                pc,
+                coin,
+                log: ProgramLogInfo::RayCpSwap(log),
+            })
+        }
+    }
+    /// PumpSwap buy and sell events, quote is the pc side
+    fn from_pump_amm(bytes: &[u8]) -> Option<Self> {
+        let code = bytes.get(..8)?;
+        let mut data = bytes[8..].to_vec();
+        if data.len() < PUMP_AMM_EVENT_LEN {
+            data.resize(PUMP_AMM_EVENT_LEN, 0);
+        }
+        if code == PUMP_AMM_BUY_EVENT_CODE.as_slice() {
+            let log = bincode::deserialize::<PumpAmmBuyLog>(&data).ok()?;
+            Some(ProgramLog {
+                // the lp fee stays in the pool
+                next_pc: log.pool_quote_token_reserves + log.quote_amount_in_with_lp_fee,
+                next_coin: log.pool_base_token_reserves.saturating_sub(log.base_amount_out),
+                amount_out: log.base_amount_out,
+                pc: log.pool_quote_token_reserves,
+                coin: log.pool_base_token_reserves,
+                log: ProgramLogInfo::PumpAmmBuy(log),
+            })
+        } else if code == PUMP_AMM_SELL_EVENT_CODE.as_slice() {
+            let log = bincode::deserialize::<PumpAmmSellLog>(&data).ok()?;
+            Some(ProgramLog {
+                next_pc: log.pool_quote_token_reserves.saturating_sub(log.quote_amount_out_without_lp_fee),
+                next_coin: log.pool_base_token_reserves + log.base_amount_in,
+                amount_out: log.user_quote_amount_out,
+                pc: log.pool_quote_token_reserves,
+                coin: log.pool_base_token_reserves,
+                log: ProgramLogInfo::PumpAmmSell(log),
+            })
+        } else {
+            None
+        }
+    }
+    /// Pool the event is about, none for logs that don't name it
+    pub fn pool(&self) -> Option<Pubkey> {
+        match &self.log {
+            ProgramLogInfo::RayCpSwap(x) => Some(x.pool_id),
+            ProgramLogInfo::RayClmmSwap(x) => Some(x.pool_state),
+            ProgramLogInfo::PumpAmmBuy(x) => Some(x.pool),
+            ProgramLogInfo::PumpAmmSell(x) => Some(x.pool),
+            _ => None,
+        }
+    }
+    /// Pool the swap event is for, the curve's trade event names its mint instead
+    pub fn pool_or_mint(&self) -> Option<Pubkey> {
+        match &self.log {
+            ProgramLogInfo::PumpTradeLog(x) => Some(x.mint),
+            _ => self.pool(),
+        }
+    }
     pub fn from(mut log:String)->anyhow::Result<Self>{
         log = log.replace("Program log: ray_log: ", "");
         log = log.replace("Program data: ", "");
@@ -158,8 +405,23 @@ impl ProgramLog {
             Ok(l)
         } else if let Some(l) = Self::from_pump(bytes.as_slice()) {
             Ok(l)
+        } else if let Some(l) = Self::from_ray_pool(bytes.as_slice()) {
+            Ok(l)
+        } else if let Some(l) = Self::from_pump_amm(bytes.as_slice()) {
+            Ok(l)
         } else {
             Err(anyhow::format_err!("cannot decode log {log}"))
         }
     }
-}
\ No newline at end of file
+    /// Pump create and complete events of a transaction. A create comes before the dev buy's
+    /// trade event, a complete right after the trade event that emptied the curve.
+    pub fn pump_events(log_messages: &[String]) -> Vec<Self> {
+        log_messages
+            .iter()
+            .filter_map(|x| x.strip_prefix("Program data: "))
+            .filter_map(|x| BASE64_STANDARD.decode(x).ok())
+            .filter_map(|x| Self::from_pump(&x))
+            .filter(|x| matches!(x.log, ProgramLogInfo::PumpCreateLog(_) | ProgramLogInfo::PumpCompleteLog(_)))
+            .collect()
+    }
+}
@@ -11,6 +11,8 @@ pub struct PumpTrade {
     pub ata_curve:Pubkey,
     pub ata_user:Pubkey,
     pub user:Pubkey,
+    /// spl-token or Token-2022, whichever owns the mint
+    pub token_program:Pubkey,
 }
 
 
@@ -29,7 +31,7 @@ pub fn pump_swap(
     data: PumpTrade,
     buy: bool
 ) -> Result<Instruction, ProgramError> {
-    let PumpTrade { data,user, mint, curve, ata_curve, ata_user } = data;
+    let PumpTrade { data,user, mint, curve, ata_curve, ata_user, token_program } = data;
 
     let min = if {buy}{data.min} else {data.max};
     let max = if {buy}{data.max} else {data.min};
@@ -49,8 +51,8 @@ pub fn pump_swap(
         AccountMeta::new(ata_user, false),
         AccountMeta::new(user, true),
         AccountMeta::new_readonly(SOLANA_SYSTEM_PROGRAM, false),
-        if buy {AccountMeta::new_readonly(spl_token::id(), false)} else {AccountMeta::new_readonly(SOLANA_ATA_PROGRAM, false)},
-        if buy {AccountMeta::new_readonly(SOLANA_RENT_PROGRAM, false)} else {AccountMeta::new_readonly(spl_token::id(), false)},
+        if buy {AccountMeta::new_readonly(token_program, false)} else {AccountMeta::new_readonly(SOLANA_ATA_PROGRAM, false)},
+        if buy {AccountMeta::new_readonly(SOLANA_RENT_PROGRAM, false)} else {AccountMeta::new_readonly(token_program, false)},
         AccountMeta::new_readonly(PUMP_EVENT_AUTHORITY, false),
         AccountMeta::new_readonly(PUMP_PROGRAM, false),
     ];
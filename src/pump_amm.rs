@@ -0,0 +1,104 @@
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::pubkey::Pubkey;
+use spl_associated_token_account_client::address::get_associated_token_address_with_program_id;
+use crate::constant::*;
+use crate::trade::PumpAmm;
+
+/// offset of the coin creator in a PumpSwap pool account
+const POOL_COIN_CREATOR_OFFSET: usize = 211;
+/// offset of the decimals in a mint account, same for both token programs
+const MINT_DECIMALS_OFFSET: usize = 44;
+
+pub fn pump_amm_creator_vault_authority(coin_creator: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"creator_vault", coin_creator.as_ref()], &PUMP_AMM_PROGRAM).0
+}
+
+/// Pool the pump migration creates for a graduated coin, index 0 and owned by the curve's pool authority
+pub fn pump_amm_canonical_pool(mint: &Pubkey) -> Pubkey {
+    let pool_authority = Pubkey::find_program_address(&[b"pool-authority", mint.as_ref()], &PUMP_PROGRAM).0;
+    Pubkey::find_program_address(
+        &[b"pool", &0u16.to_le_bytes(), pool_authority.as_ref(), mint.as_ref(), SOLANA_MINT.as_ref()],
+        &PUMP_AMM_PROGRAM,
+    )
+    .0
+}
+
+pub fn pump_amm_user_volume_accumulator(user: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"user_volume_accumulator", user.as_ref()], &PUMP_AMM_PROGRAM).0
+}
+
+/// PumpSwap `buy` takes exact tokens out for at most `quote_amount` lamports in,
+/// `sell` exact tokens in for at least `quote_amount` lamports out
+pub fn pump_amm_swap(
+    pool: &PumpAmm,
+    user: &Pubkey,
+    wsol: &Pubkey,
+    token: &Pubkey,
+    buy: bool,
+    base_amount: u64,
+    quote_amount: u64,
+) -> Instruction {
+    let data = [
+        if buy { PUMP_BUY_CODE } else { PUMP_SELL_CODE }.as_slice(),
+        &base_amount.to_le_bytes(),
+        &quote_amount.to_le_bytes(),
+        // buy's `track_volume`, no program version reads past its args
+        if buy { &[0u8][..] } else { &[] },
+    ]
+    .concat();
+    let creator_vault_authority = pool
+        .coin_creator_vault_authority
+        .unwrap_or_else(|| pump_amm_creator_vault_authority(&Pubkey::default()));
+    let mut accounts = vec![
+        AccountMeta::new(pool.pool, false),
+        AccountMeta::new(*user, true),
+        AccountMeta::new_readonly(PUMP_AMM_GLOBAL_CONFIG, false),
+        AccountMeta::new_readonly(pool.base_mint, false),
+        AccountMeta::new_readonly(SOLANA_MINT, false),
+        AccountMeta::new(*token, false),
+        AccountMeta::new(*wsol, false),
+        AccountMeta::new(pool.pool_base_token_account, false),
+        AccountMeta::new(pool.pool_quote_token_account, false),
+        AccountMeta::new_readonly(pool.protocol_fee_recipient, false),
+        AccountMeta::new(
+            get_associated_token_address_with_program_id(&pool.protocol_fee_recipient, &SOLANA_MINT, &spl_token::id()),
+            false,
+        ),
+        AccountMeta::new_readonly(pool.base_token_program, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(SOLANA_SYSTEM_PROGRAM, false),
+        AccountMeta::new_readonly(SOLANA_ATA_PROGRAM, false),
+        AccountMeta::new_readonly(PUMP_AMM_EVENT_AUTHORITY, false),
+        AccountMeta::new_readonly(PUMP_AMM_PROGRAM, false),
+        AccountMeta::new(
+            get_associated_token_address_with_program_id(&creator_vault_authority, &SOLANA_MINT, &spl_token::id()),
+            false,
+        ),
+        AccountMeta::new_readonly(creator_vault_authority, false),
+    ];
+    // added by later program versions, the ones before take them as remaining accounts
+    if buy {
+        accounts.push(AccountMeta::new(PUMP_AMM_GLOBAL_VOLUME_ACCUMULATOR, false));
+        accounts.push(AccountMeta::new(pump_amm_user_volume_accumulator(user), false));
+    }
+    accounts.push(AccountMeta::new_readonly(PUMP_AMM_FEE_CONFIG, false));
+    accounts.push(AccountMeta::new_readonly(PUMP_FEE_PROGRAM, false));
+    Instruction {
+        program_id: PUMP_AMM_PROGRAM,
+        accounts,
+        data,
+    }
+}
+
+/// Coin creator from a PumpSwap pool account, the default key for pools from before creator fees
+pub fn pump_amm_pool_coin_creator(data: &[u8]) -> Option<Pubkey> {
+    let bytes: [u8; 32] = data
+        .get(POOL_COIN_CREATOR_OFFSET..POOL_COIN_CREATOR_OFFSET + 32)?
+        .try_into()
+        .ok()?;
+    Some(Pubkey::new_from_array(bytes))
+}
+
+pub fn mint_decimals(data: &[u8]) -> Option<u8> {
+    data.get(MINT_DECIMALS_OFFSET).copied()
+}
@@ -0,0 +1,270 @@
+use raydium_amm::math::{Calculator, SwapDirection, U128};
+use rust_decimal::Decimal;
+use rust_decimal::MathematicalOps;
+use rust_decimal_macros::dec;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+use crate::constant::*;
+use crate::plog::ProgramLog;
+use crate::trade::{RayClmm, RayCpmm, TradePool};
+
+/// Constant product reserves of a pool, raw units
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reserves {
+    pub sol: u64,
+    pub token: u64,
+    pub fee_bps: u64,
+    /// pump takes its fee in SOL on either side, raydium from the input
+    pub fee_on_sol: bool,
+}
+
+impl Reserves {
+    /// Reserves the swap in `plog` left in `pool`, none until the trade knows its pool.
+    /// CLMM reserves are the virtual ones inside the current tick.
+    pub fn new(pool: &TradePool, plog: &ProgramLog) -> Option<Reserves> {
+        // an init log only has the opening amounts
+        let (pc, coin) = if plog.next_pc == 0 && plog.next_coin == 0 {
+            (plog.pc, plog.coin)
+        } else {
+            (plog.next_pc, plog.next_coin)
+        };
+        let (sol, token, fee_bps, fee_on_sol) = match *pool {
+            TradePool::RayAmm4(amm) => {
+                if amm.pc_mint == SOLANA_MINT {
+                    (pc, coin, RAY_AMM4_FEE_BPS, false)
+                } else {
+                    (coin, pc, RAY_AMM4_FEE_BPS, false)
+                }
+            }
+            TradePool::PumpBondingCurve(_) => (pc, coin, PUMP_FEE_BPS, true),
+            TradePool::PumpAmm(z) => (pc, coin, z.fee_bps.unwrap_or(PUMP_AMM_FEE_BPS), true),
+            // token 0 is the coin side
+            TradePool::RayCpmm(RayCpmm { token_0_mint, trade_fee_rate, .. })
+            | TradePool::RayClmm(RayClmm { token_0_mint, trade_fee_rate, .. }) => {
+                let fee_bps = trade_fee_rate.unwrap_or(RAY_DEFAULT_FEE_RATE) / 100;
+                if token_0_mint == SOLANA_MINT {
+                    (coin, pc, fee_bps, false)
+                } else {
+                    (pc, coin, fee_bps, false)
+                }
+            }
+            TradePool::Generic(_) | TradePool::PumpBondingCurveMint(_) => return None,
+        };
+        if sol == 0 || token == 0 {
+            return None;
+        }
+        Some(Reserves { sol, token, fee_bps, fee_on_sol })
+    }
+
+    /// SOL per token before any swap
+    pub fn spot_price(&self, decimals: i16) -> Decimal {
+        ui_price(self.sol, self.token, decimals)
+    }
+
+    fn fee(&self, amount: u64) -> u64 {
+        (amount as u128 * self.fee_bps as u128 / 10_000) as u64
+    }
+
+    /// What's left of `amount` once the fee is taken from it
+    fn less_fee(&self, amount: u64) -> u64 {
+        amount - self.fee(amount)
+    }
+
+    /// The amount that is `amount` once the fee is taken from it, rounded up
+    fn with_fee(&self, amount: u64) -> u64 {
+        (amount as u128 * 10_000).div_ceil(10_000 - self.fee_bps as u128) as u64
+    }
+}
+
+/// Expected fill of a swap against a pool's reserves, before network fees.
+/// A buy puts lamports in for raw tokens, a sell raw tokens in for lamports.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct Quote {
+    pub buy: bool,
+    pub amount_in: u64,
+    /// after the pool's fee
+    pub amount_out: u64,
+    /// SOL per token before the swap
+    pub spot_price: Decimal,
+    /// SOL per token the swap fills at, fee included
+    pub price: Decimal,
+    /// how much worse `price` is than `spot_price`, 0.01 is 1%
+    pub price_impact: Decimal,
+}
+
+impl Quote {
+    /// Output of exactly `amount_in`
+    pub fn base_in(reserves: Reserves, amount_in: u64, buy: bool, decimals: i16) -> Option<Quote> {
+        if amount_in == 0 {
+            return None;
+        }
+        let (sol, token) = (U128::from(reserves.sol), U128::from(reserves.token));
+        let amount_out = if buy {
+            let amount_in = reserves.less_fee(amount_in);
+            Calculator::swap_token_amount_base_in(U128::from(amount_in), sol, token, SwapDirection::PC2Coin).as_u64()
+        } else if reserves.fee_on_sol {
+            let out = Calculator::swap_token_amount_base_in(U128::from(amount_in), sol, token, SwapDirection::Coin2PC);
+            reserves.less_fee(out.as_u64())
+        } else {
+            let amount_in = reserves.less_fee(amount_in);
+            Calculator::swap_token_amount_base_in(U128::from(amount_in), sol, token, SwapDirection::Coin2PC).as_u64()
+        };
+        Self::new(reserves, buy, amount_in, amount_out, decimals)
+    }
+
+    /// Input that gets exactly `amount_out`, none when the pool doesn't hold that much
+    pub fn base_out(reserves: Reserves, amount_out: u64, buy: bool, decimals: i16) -> Option<Quote> {
+        let (sol, token) = (U128::from(reserves.sol), U128::from(reserves.token));
+        let amount_in = if buy {
+            if amount_out == 0 || amount_out >= reserves.token {
+                return None;
+            }
+            let amount_in = Calculator::swap_token_amount_base_out(U128::from(amount_out), sol, token, SwapDirection::PC2Coin);
+            reserves.with_fee(amount_in.as_u64())
+        } else {
+            let gross_out = if reserves.fee_on_sol { reserves.with_fee(amount_out) } else { amount_out };
+            if amount_out == 0 || gross_out >= reserves.sol {
+                return None;
+            }
+            let amount_in = Calculator::swap_token_amount_base_out(U128::from(gross_out), sol, token, SwapDirection::Coin2PC).as_u64();
+            if reserves.fee_on_sol { amount_in } else { reserves.with_fee(amount_in) }
+        };
+        Self::new(reserves, buy, amount_in, amount_out, decimals)
+    }
+
+    fn new(reserves: Reserves, buy: bool, amount_in: u64, amount_out: u64, decimals: i16) -> Option<Quote> {
+        if amount_out == 0 {
+            return None;
+        }
+        let spot_price = reserves.spot_price(decimals);
+        let (price, price_impact) = if buy {
+            let price = ui_price(amount_in, amount_out, decimals);
+            (price, price / spot_price - dec!(1))
+        } else {
+            let price = ui_price(amount_out, amount_in, decimals);
+            (price, dec!(1) - price / spot_price)
+        };
+        Some(Quote {
+            buy,
+            amount_in,
+            amount_out,
+            spot_price,
+            price,
+            price_impact,
+        })
+    }
+}
+
+/// SOL per token of raw lamports and tokens
+fn ui_price(lamports: u64, tokens: u64, decimals: i16) -> Decimal {
+    let sol = Decimal::from(lamports) / Decimal::from(LAMPORTS_PER_SOL);
+    let tokens = Decimal::from(tokens) / dec!(10).powd(Decimal::from(decimals));
+    sol / tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::pubkey::Pubkey;
+    use crate::trade::PumpBondingCurve;
+
+    fn pump(sol: u64, token: u64) -> Reserves {
+        Reserves { sol, token, fee_bps: PUMP_FEE_BPS, fee_on_sol: true }
+    }
+
+    fn raydium(sol: u64, token: u64) -> Reserves {
+        Reserves { sol, token, fee_bps: RAY_AMM4_FEE_BPS, fee_on_sol: false }
+    }
+
+    #[test]
+    fn raydium_swap_base_in_fill() {
+        // SwapBaseInLog { amount_in: 670000000000, direction: 1, pool_coin: 9000000000000000000, pool_pc: 150000000, out_amount: 8997980477953550992 }
+        let quote = Quote::base_in(raydium(150_000_000, 9_000_000_000_000_000_000), 670_000_000_000, true, 6).unwrap();
+        assert_eq!(quote.amount_out, 8_997_980_477_953_550_992);
+    }
+
+    #[test]
+    fn raydium_swap_base_out_fill() {
+        // SwapBaseOutLog { amount_out: 2364571428, direction: 1, pool_coin: 171880473738872568, pool_pc: 843000100000, deduct_in: 11628 }
+        let quote = Quote::base_out(raydium(843_000_100_000, 171_880_473_738_872_568), 2_364_571_428, true, 6).unwrap();
+        assert_eq!(quote.amount_in, 11_628);
+    }
+
+    #[test]
+    fn pump_sell_fill() {
+        // the curve sends sol * tokens / (tokens + sold) and keeps 1% of it
+        let quote = Quote::base_in(pump(40_000_000_000, 804_000_000_000_000), 10_000_000_000_000, false, 6).unwrap();
+        assert_eq!(quote.amount_out, 486_486_487);
+    }
+
+    #[test]
+    fn pump_buy_fill() {
+        // 1 SOL on a fresh curve, 0.99 after the fee
+        let quote = Quote::base_in(pump(30_000_000_000, 1_073_000_000_000_000), 1_000_000_000, true, 6).unwrap();
+        assert_eq!(quote.amount_out, 34_277_831_558_567);
+        assert!(quote.price > quote.spot_price);
+        assert!(quote.price_impact > Decimal::ZERO);
+    }
+
+    #[test]
+    fn base_out_inverts_base_in() {
+        for reserves in [pump(30_000_000_000, 1_073_000_000_000_000), raydium(843_000_100_000, 171_880_473_738_872_568)] {
+            for buy in [true, false] {
+                let amount_in = if buy { 2_000_000_000 } else { 50_000_000_000_000 };
+                let quote = Quote::base_in(reserves, amount_in, buy, 6).unwrap();
+                let back = Quote::base_out(reserves, quote.amount_out, buy, 6).unwrap();
+                // paying the quoted input gets at least the output back
+                let again = Quote::base_in(reserves, back.amount_in, buy, 6).unwrap();
+                assert!(again.amount_out >= quote.amount_out, "{buy} {} < {}", again.amount_out, quote.amount_out);
+                assert!(back.amount_in.abs_diff(amount_in) <= amount_in / 1_000, "{buy} {} != {amount_in}", back.amount_in);
+            }
+        }
+    }
+
+    #[test]
+    fn fee_on_sol_differs_from_fee_on_input() {
+        let on_sol = pump(40_000_000_000, 804_000_000_000_000);
+        let on_input = Reserves { fee_on_sol: false, ..on_sol };
+        let free = Reserves { fee_bps: 0, ..on_sol };
+        let out = |r| Quote::base_in(r, 100_000_000_000_000, false, 6).unwrap().amount_out;
+        assert_ne!(out(on_sol), out(on_input));
+        assert!(out(on_sol) < out(free));
+        assert!(out(on_input) < out(free));
+        // buys take the fee from the SOL going in either way
+        let buy = |r| Quote::base_in(r, 1_000_000_000, true, 6).unwrap().amount_out;
+        assert_eq!(buy(on_sol), buy(on_input));
+    }
+
+    #[test]
+    fn fee_of_large_amounts() {
+        let reserves = raydium(1, 1);
+        assert_eq!(reserves.fee(u64::MAX), (u64::MAX as u128 * 25 / 10_000) as u64);
+        assert!(reserves.less_fee(u64::MAX) < u64::MAX);
+    }
+
+    #[test]
+    fn nothing_to_quote() {
+        let reserves = pump(30_000_000_000, 1_073_000_000_000_000);
+        assert_eq!(Quote::base_in(reserves, 0, true, 6), None);
+        assert_eq!(Quote::base_out(reserves, 0, true, 6), None);
+        // more than the pool holds
+        assert_eq!(Quote::base_out(reserves, reserves.token, true, 6), None);
+        assert_eq!(Quote::base_out(reserves, reserves.sol, false, 6), None);
+        // rounds down to nothing
+        assert_eq!(Quote::base_in(raydium(1, 1_000_000_000), 1, false, 6), None);
+
+        let plog = ProgramLog::default();
+        assert_eq!(Reserves::new(&TradePool::Generic(SOLANA_MINT), &plog), None);
+        let curve = TradePool::PumpBondingCurve(PumpBondingCurve {
+            amm: Pubkey::new_unique(),
+            vault: Pubkey::new_unique(),
+            mint: Pubkey::new_unique(),
+            decimals: Some(6),
+            token_program_id: spl_token::id(),
+        });
+        assert_eq!(Reserves::new(&curve, &plog), None);
+        let plog = ProgramLog { next_pc: 30_000_000_000, next_coin: 1_073_000_000_000_000, ..Default::default() };
+        assert_eq!(Reserves::new(&curve, &plog), Some(reserves));
+    }
+}
@@ -0,0 +1,230 @@
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::pubkey::Pubkey;
+use crate::constant::*;
+use crate::trade::{RayClmm, RayCpmm};
+
+/// ticks in one CLMM tick array
+const TICK_ARRAY_SIZE: i32 = 60;
+/// initialized tick arrays passed to a CLMM swap, the price can cross into the ones after the first
+const CLMM_TICK_ARRAYS: usize = 3;
+/// tick arrays on either side of tick 0 the pool's own bitmap covers, the rest are in the extension
+const TICK_ARRAY_BITMAP_HALF: i32 = 512;
+
+pub fn ray_cp_authority() -> Pubkey {
+    Pubkey::find_program_address(&[b"vault_and_lp_mint_auth_seed"], &RAYDIUM_CP_PROGRAM).0
+}
+
+/// CP-Swap `swap_base_input`, `buy` swaps SOL in for the token
+pub fn ray_cp_swap(
+    pool: &RayCpmm,
+    user: &Pubkey,
+    wsol: &Pubkey,
+    token: &Pubkey,
+    buy: bool,
+    amount_in: u64,
+    min_out: u64,
+) -> Instruction {
+    let sol_is_0 = pool.token_0_mint == SOLANA_MINT;
+    let (sol_vault, token_vault) = if sol_is_0 {
+        (pool.token_0_vault, pool.token_1_vault)
+    } else {
+        (pool.token_1_vault, pool.token_0_vault)
+    };
+    let (token_mint, token_program) = if sol_is_0 {
+        (pool.token_1_mint, pool.token_1_program)
+    } else {
+        (pool.token_0_mint, pool.token_0_program)
+    };
+    let sol = (*wsol, sol_vault, spl_token::id(), SOLANA_MINT);
+    let coin = (*token, token_vault, token_program, token_mint);
+    let (input, output) = if buy { (sol, coin) } else { (coin, sol) };
+
+    let data = [
+        RAYDIUM_CP_SWAP_BASE_INPUT_CODE.as_slice(),
+        &amount_in.to_le_bytes(),
+        &min_out.to_le_bytes(),
+    ]
+    .concat();
+    let accounts = vec![
+        AccountMeta::new_readonly(*user, true),
+        AccountMeta::new_readonly(ray_cp_authority(), false),
+        AccountMeta::new_readonly(pool.amm_config, false),
+        AccountMeta::new(pool.pool, false),
+        AccountMeta::new(input.0, false),
+        AccountMeta::new(output.0, false),
+        AccountMeta::new(input.1, false),
+        AccountMeta::new(output.1, false),
+        AccountMeta::new_readonly(input.2, false),
+        AccountMeta::new_readonly(output.2, false),
+        AccountMeta::new_readonly(input.3, false),
+        AccountMeta::new_readonly(output.3, false),
+        AccountMeta::new(pool.observation, false),
+    ];
+    Instruction {
+        program_id: RAYDIUM_CP_PROGRAM,
+        accounts,
+        data,
+    }
+}
+
+/// CLMM `swap_v2` with exact input, `buy` swaps SOL in for the token.
+/// Tick arrays are picked from `tick_current` and `tick_array_bitmap`, see [clmm_tick_arrays].
+pub fn ray_clmm_swap(
+    pool: &RayClmm,
+    user: &Pubkey,
+    wsol: &Pubkey,
+    token: &Pubkey,
+    buy: bool,
+    amount_in: u64,
+    min_out: u64,
+) -> Instruction {
+    let sol_is_0 = pool.token_0_mint == SOLANA_MINT;
+    // token 0 in moves the price down
+    let zero_for_one = buy == sol_is_0;
+    let (input, output) = if zero_for_one {
+        ((pool.token_0_vault, pool.token_0_mint), (pool.token_1_vault, pool.token_1_mint))
+    } else {
+        ((pool.token_1_vault, pool.token_1_mint), (pool.token_0_vault, pool.token_0_mint))
+    };
+    let (input_account, output_account) = if buy { (wsol, token) } else { (token, wsol) };
+
+    let data = [
+        RAYDIUM_CLMM_SWAP_V2_CODE.as_slice(),
+        &amount_in.to_le_bytes(),
+        &min_out.to_le_bytes(),
+        // no price limit
+        &0u128.to_le_bytes(),
+        &[1u8],
+    ]
+    .concat();
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*user, true),
+        AccountMeta::new_readonly(pool.amm_config, false),
+        AccountMeta::new(pool.pool, false),
+        AccountMeta::new(*input_account, false),
+        AccountMeta::new(*output_account, false),
+        AccountMeta::new(input.0, false),
+        AccountMeta::new(output.0, false),
+        AccountMeta::new(pool.observation, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(spl_token_2022::id(), false),
+        AccountMeta::new_readonly(SOLANA_MEMO_PROGRAM, false),
+        AccountMeta::new_readonly(input.1, false),
+        AccountMeta::new_readonly(output.1, false),
+        AccountMeta::new(clmm_bitmap_extension(&pool.pool), false),
+    ];
+    accounts.extend(
+        clmm_tick_arrays(
+            &pool.pool,
+            pool.tick_current.unwrap_or_default(),
+            pool.tick_spacing.unwrap_or(1),
+            zero_for_one,
+            pool.tick_array_bitmap.as_ref(),
+        )
+        .into_iter()
+        .map(|x| AccountMeta::new(x, false)),
+    );
+    Instruction {
+        program_id: RAYDIUM_CLMM_PROGRAM,
+        accounts,
+        data,
+    }
+}
+
+pub fn clmm_bitmap_extension(pool: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"pool_tick_array_bitmap_extension", pool.as_ref()],
+        &RAYDIUM_CLMM_PROGRAM,
+    )
+    .0
+}
+
+/// The initialized tick arrays a swap from `tick` goes through, in order. Arrays are only
+/// created once liquidity is added in them, so they come from the pool's bitmap. Without it,
+/// or past the arrays it covers, only the array holding `tick` is passed.
+pub fn clmm_tick_arrays(
+    pool: &Pubkey,
+    tick: i32,
+    tick_spacing: u16,
+    zero_for_one: bool,
+    bitmap: Option<&[u64; 16]>,
+) -> Vec<Pubkey> {
+    let ticks_in_array = TICK_ARRAY_SIZE * tick_spacing as i32;
+    let current = tick.div_euclid(ticks_in_array);
+    let step = if zero_for_one { -1 } else { 1 };
+    let mut indexes = match bitmap {
+        Some(bitmap) => (0..)
+            .map(|i| current + step * i)
+            .take_while(|i| (-TICK_ARRAY_BITMAP_HALF..TICK_ARRAY_BITMAP_HALF).contains(i))
+            .filter(|i| {
+                let bit = (i + TICK_ARRAY_BITMAP_HALF) as usize;
+                bitmap[bit / 64] >> (bit % 64) & 1 == 1
+            })
+            .take(CLMM_TICK_ARRAYS)
+            .collect::<Vec<_>>(),
+        None => vec![],
+    };
+    if indexes.is_empty() {
+        indexes.push(current);
+    }
+    indexes
+        .into_iter()
+        .map(|i| {
+            Pubkey::find_program_address(
+                &[b"tick_array", pool.as_ref(), &(i * ticks_in_array).to_be_bytes()],
+                &RAYDIUM_CLMM_PROGRAM,
+            )
+            .0
+        })
+        .collect()
+}
+
+fn u8_at(data: &[u8], offset: usize) -> Option<u8> {
+    data.get(offset).copied()
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(offset..offset + 2)?.try_into().ok()?))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+fn u64_at(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(data.get(offset..offset + 8)?.try_into().ok()?))
+}
+
+/// Mint decimals of token 0 and 1 from a CP-Swap pool account
+pub fn cp_pool_decimals(data: &[u8]) -> Option<(u8, u8)> {
+    Some((u8_at(data, 331)?, u8_at(data, 332)?))
+}
+
+/// Trade fee in millionths from a CP-Swap amm config account
+pub fn cp_config_fee_rate(data: &[u8]) -> Option<u64> {
+    u64_at(data, 12)
+}
+
+/// Mint decimals of token 0 and 1, tick spacing and current tick from a CLMM pool account
+pub fn clmm_pool_state(data: &[u8]) -> Option<(u8, u8, u16, i32)> {
+    Some((
+        u8_at(data, 233)?,
+        u8_at(data, 234)?,
+        u16_at(data, 235)?,
+        u32_at(data, 269)? as i32,
+    ))
+}
+
+/// Initialized tick arrays around tick 0 from a CLMM pool account, one bit per array
+pub fn clmm_pool_bitmap(data: &[u8]) -> Option<[u64; 16]> {
+    let mut bitmap = [0u64; 16];
+    for (i, word) in bitmap.iter_mut().enumerate() {
+        *word = u64_at(data, 904 + i * 8)?;
+    }
+    Some(bitmap)
+}
+
+/// Trade fee in millionths from a CLMM amm config account
+pub fn clmm_config_fee_rate(data: &[u8]) -> Option<u64> {
+    u32_at(data, 47).map(u64::from)
+}
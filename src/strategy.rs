@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use rust_decimal::Decimal;
+use raydium_amm::solana_program::native_token::LAMPORTS_PER_SOL;
+use crate::plog::*;
+use crate::trade::*;
+use crate::trade_cache::Cache;
+use crate::trade_chan::InterestedTx;
+
+pub const DEFAULT_STRATEGY: &str = "tp_sl";
+pub const PUMP_MIN_BUY_STRATEGY: &str = "pump_min_buy";
+
+/// What a strategy wants trade_chan to do with a trade after a decoded log
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StrategyIntent {
+    Buy,
+    /// fraction of the tokens held to sell, 1 closes the position
+    Sell(Decimal),
+    Hold,
+}
+
+/// Entry and exit decisions, called by trade_chan for every log touching the trade's mint.
+/// The trade already has `price`, `pct` and `plog` updated from the log.
+pub trait Strategy: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn on_log(&self, trade: &Trade, tx: &InterestedTx, db: &Cache) -> StrategyIntent;
+}
+
+/// Buys on the next log after the trade is cached, sells with `Trade::exit_fraction`
+pub struct TpSlStrategy;
+
+impl Strategy for TpSlStrategy {
+    fn name(&self) -> &'static str {
+        DEFAULT_STRATEGY
+    }
+    fn on_log(&self, trade: &Trade, _tx: &InterestedTx, _db: &Cache) -> StrategyIntent {
+        match trade.state {
+            TradeState::Buy => StrategyIntent::Buy,
+            TradeState::BuySuccess | TradeState::PartiallyClosed => trade
+                .exit_fraction()
+                .map(StrategyIntent::Sell)
+                .unwrap_or(StrategyIntent::Hold),
+            _ => StrategyIntent::Hold,
+        }
+    }
+}
+
+/// Only enters after someone else buys at least `min_sol` lamports on the curve,
+/// exits the same way as [TpSlStrategy]
+pub struct PumpMinBuyStrategy {
+    pub min_sol: u64,
+}
+
+impl Strategy for PumpMinBuyStrategy {
+    fn name(&self) -> &'static str {
+        PUMP_MIN_BUY_STRATEGY
+    }
+    fn on_log(&self, trade: &Trade, tx: &InterestedTx, db: &Cache) -> StrategyIntent {
+        match trade.state {
+            TradeState::Buy => {
+                if let ProgramLogInfo::PumpTradeLog(log) = trade.plog.log {
+                    if log.is_buy && log.sol_amount >= self.min_sol {
+                        return StrategyIntent::Buy;
+                    }
+                }
+                StrategyIntent::Hold
+            }
+            _ => TpSlStrategy.on_log(trade, tx, db),
+        }
+    }
+}
+
+/// Strategies by name. A trade picks one with `Trade::strategy`,
+/// which the api fills from the buy request or the user's default.
+pub struct Strategies {
+    strategies: HashMap<String, Box<dyn Strategy>>,
+}
+
+impl Default for Strategies {
+    fn default() -> Self {
+        let mut s = Strategies {
+            strategies: HashMap::new(),
+        };
+        s.register(Box::new(TpSlStrategy));
+        s.register(Box::new(PumpMinBuyStrategy {
+            min_sol: LAMPORTS_PER_SOL,
+        }));
+        s
+    }
+}
+
+impl Strategies {
+    pub fn register(&mut self, strategy: Box<dyn Strategy>) {
+        self.strategies.insert(strategy.name().to_string(), strategy);
+    }
+    /// Unknown names fall back to [DEFAULT_STRATEGY]
+    pub fn get(&self, trade: &Trade) -> &dyn Strategy {
+        trade
+            .strategy
+            .as_ref()
+            .and_then(|x| self.strategies.get(x))
+            .or_else(|| self.strategies.get(DEFAULT_STRATEGY))
+            .map(|x| x.as_ref())
+            .unwrap()
+    }
+    pub fn on_log(&self, trade: &Trade, tx: &InterestedTx, db: &Cache) -> StrategyIntent {
+        self.get(trade).on_log(trade, tx, db)
+    }
+}
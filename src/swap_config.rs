@@ -2,6 +2,7 @@ use rust_decimal::Decimal;
 use rust_decimal_macros::dec;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use crate::trade::TipStatistics;
 
 #[derive(Clone,Debug,Copy,Serialize,Deserialize,JsonSchema)]
 pub struct PositionConfig {
@@ -23,7 +24,7 @@ pub struct PositionConfig {
     /// what % fees should go to jito
     pub jito_tip_percent: Decimal,
     #[schemars(skip)]
-    /// compute unit limit e.g 100k
+    /// compute unit limit e.g 100k, 0 uses the gas limits below
     pub cu_limit: Decimal,
     #[schemars(skip)]
     /// percentage of max_sol to fall to slippage
@@ -43,6 +44,106 @@ pub struct PositionConfig {
     #[schemars(skip)]
     /// How much of profit is for fees
     pub fee_pct: Decimal,
+    #[schemars(skip)]
+    #[serde(default)]
+    /// Sell everything when price falls this far below the highest price since buy e.g 0.1, 0 is off
+    pub trailing_sl: Decimal,
+    #[schemars(skip)]
+    #[serde(default)]
+    /// Take profit levels, checked in order, a level with 0 fraction is off.
+    /// Each sells its fraction of what is left after the levels before it.
+    /// While levels are left `tp` is ignored
+    pub tp_ladder: [TakeProfitLevel; TP_LADDER_LEN],
+    #[schemars(skip)]
+    #[serde(default)]
+    /// How the jito tip is sized, live tips are capped at `max_jito`
+    pub jito_tip_policy: JitoTipPolicy,
+    #[schemars(skip)]
+    #[serde(default)]
+    /// Send the tip as its own transaction in a jito bundle with the swap
+    pub jito_bundle: bool,
+    #[schemars(skip)]
+    #[serde(default)]
+    /// Simulate before sending, sets `cu_limit` from the consumed units
+    pub simulate: bool,
+    #[schemars(skip)]
+    #[serde(default)]
+    /// extra compute units over the simulated ones e.g 0.2
+    pub cu_margin: Decimal,
+    #[schemars(skip)]
+    #[serde(default)]
+    /// resend a pending swap this often until its blockhash expires, 0 is off
+    pub rebroadcast_ms: u64,
+    #[schemars(skip)]
+    #[serde(default)]
+    /// rebuilds with a fresh blockhash after expiry before the swap is failed
+    pub max_retries: u32,
+    #[schemars(skip)]
+    #[serde(default)]
+    /// fee raise per retry e.g 0.5 is 50% more each time
+    pub fee_bump: Decimal,
+    #[schemars(skip)]
+    #[serde(default)]
+    /// Build v0 transactions against the wallet's address lookup table
+    pub versioned: bool,
+    #[schemars(skip)]
+    #[serde(default)]
+    /// Presign exits against the wallet's durable nonce once a buy lands
+    pub durable_nonce: bool,
+    #[schemars(skip)]
+    #[serde(default)]
+    /// CLMM swaps walk ticks and need more units than the AMM ones
+    pub ray_clmm_gas_limit: u64,
+}
+
+pub const TP_LADDER_LEN: usize = 4;
+
+#[derive(Clone,Debug,Copy,Serialize,Deserialize,JsonSchema,Default,PartialEq)]
+pub struct TakeProfitLevel {
+    /// pnl percent that triggers this level e.g 0.5
+    pub pct: Decimal,
+    /// fraction of the tokens still held to sell e.g 0.25
+    pub fraction: Decimal,
+}
+
+#[derive(Clone,Debug,Copy,Serialize,Deserialize,JsonSchema,Default,PartialEq,Eq)]
+pub enum JitoTipPolicy {
+    /// `jito_tip_percent` / 2 of the fee budget
+    #[default]
+    Fixed,
+    /// landed tips percentile from the tip stream: 25, 50, 75, 95 or 99
+    Percentile(#[serde(deserialize_with = "de_tip_percentile")] u8),
+    /// ema of the landed tips 50th percentile
+    Ema,
+    /// always `max_jito`
+    Max,
+}
+
+pub const TIP_PERCENTILES: [u8; 5] = [25, 50, 75, 95, 99];
+
+fn de_tip_percentile<'de, D: serde::Deserializer<'de>>(d: D) -> Result<u8, D::Error> {
+    let p = u8::deserialize(d)?;
+    if !TIP_PERCENTILES.contains(&p) {
+        return Err(serde::de::Error::custom(format!(
+            "tip percentile {p} not in {TIP_PERCENTILES:?}"
+        )));
+    }
+    Ok(p)
+}
+
+impl JitoTipPolicy {
+    /// Tip in SOL from the tip stream, none for policies that don't use it
+    pub fn tip_sol_ui(&self, tip: &TipStatistics) -> Option<Decimal> {
+        match self {
+            JitoTipPolicy::Percentile(25) => Some(tip.landed_tips_25th_percentile),
+            JitoTipPolicy::Percentile(50) => Some(tip.landed_tips_50th_percentile),
+            JitoTipPolicy::Percentile(75) => Some(tip.landed_tips_75th_percentile),
+            JitoTipPolicy::Percentile(95) => Some(tip.landed_tips_95th_percentile),
+            JitoTipPolicy::Percentile(99) => Some(tip.landed_tips_99th_percentile),
+            JitoTipPolicy::Ema => Some(tip.ema_landed_tips_50th_percentile),
+            _ => None,
+        }
+    }
 }
 
 impl Default for PositionConfig {
@@ -66,6 +167,18 @@ impl Default for PositionConfig {
             tp: dec!(0),
             sl: dec!(0),
             fee_pct: dec!(0.2),
+            trailing_sl: dec!(0),
+            tp_ladder: Default::default(),
+            jito_tip_policy: Default::default(),
+            jito_bundle: false,
+            simulate: false,
+            cu_margin: dec!(0.2),
+            rebroadcast_ms: 2_000,
+            max_retries: 2,
+            fee_bump: dec!(0.5),
+            versioned: false,
+            durable_nonce: false,
+            ray_clmm_gas_limit: 200_000,
         }
     }
 }
@@ -83,4 +196,18 @@ impl PositionConfig {
     pub fn total_minus_fees_normal(&self) ->Decimal {
         self.max_sol
     }
+    /// Ladder level at `index` if it is on
+    pub fn tp_level(&self, index: usize) -> Option<TakeProfitLevel> {
+        self.tp_ladder
+            .get(index)
+            .filter(|x| x.fraction > Decimal::ZERO)
+            .copied()
+    }
+    /// Copies up to [TP_LADDER_LEN] levels, the rest are dropped
+    pub fn set_tp_ladder(&mut self, levels: &[TakeProfitLevel]) {
+        self.tp_ladder = Default::default();
+        for (i, level) in levels.iter().take(TP_LADDER_LEN).enumerate() {
+            self.tp_ladder[i] = *level;
+        }
+    }
 }
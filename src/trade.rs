@@ -4,13 +4,14 @@ use raydium_amm::solana_program::native_token::{sol_to_lamports, LAMPORTS_PER_SO
 use rust_decimal::prelude::ToPrimitive;
 use rust_decimal::{Decimal, MathematicalOps};
 use rust_decimal_macros::dec;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use solana_sdk::compute_budget::ComputeBudgetInstruction;
+use solana_sdk::hash::Hash;
 use solana_sdk::instruction::{AccountMeta, Instruction};
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::signature::{Keypair, Signature};
 use solana_sdk::signer::Signer;
-use solana_sdk::transaction::Transaction;
+use solana_sdk::transaction::{Transaction, VersionedTransaction};
 use spl_associated_token_account_client::address::get_associated_token_address_with_program_id;
 use std::cmp::{max, min};
 use std::fmt;
@@ -28,8 +29,9 @@ use tokio::sync::oneshot;
 use tokio::task::JoinHandle;
 use crate::plog::ProgramLog;
 use crate::implement_mongo_crud_struct;
-use crate::swap_config::PositionConfig;
+use crate::swap_config::{JitoTipPolicy, PositionConfig, TakeProfitLevel};
 use crate::trade_rpc::*;
+use crate::wallet::WalletSelection;
 
 
 #[derive(Clone, Debug,Serialize,Deserialize)]
@@ -52,9 +54,64 @@ pub struct Trade {
     pub k: Decimal,
     pub tvl:Decimal,
     pub cfg: PositionConfig,
+    /// name of the strategy in `Strategies`, default strategy when none
+    pub strategy: Option<String>,
+    /// entry condition for a limit buy, market buy when none
+    pub limit: Option<LimitOrder>,
+    /// paper trade, fills are simulated from the next log and nothing is sent
+    #[serde(default)]
+    pub paper: bool,
     pub error: Option<String>,
     pub plog:ProgramLog,
     pub buy_out: Option<u64>,
+    /// highest price since buy, used by the trailing stop
+    pub high_price: Option<Decimal>,
+    /// number of `cfg.tp_ladder` levels already sold
+    #[serde(default)]
+    pub tp_level: usize,
+    /// tokens to sell in the pending sell, everything when none
+    pub sell_amount: Option<u64>,
+    /// `cfg.tp_ladder` level the pending sell fills, none for other sells
+    pub sell_tp_level: Option<usize>,
+    /// every landed buy and sell, oldest first
+    #[serde(default)]
+    pub fills: Vec<TradeFill>,
+    /// latest tip stream stats when the swap was built
+    pub jito_tip: Option<TipStatistics>,
+    /// signed transactions that land right before the swap in the same bundle, e.g. a known buy
+    #[serde(skip)]
+    pub bundle_head: Vec<VersionedTransaction>,
+    /// block height the pending swap's blockhash expires at
+    pub last_valid_block_height: Option<u64>,
+    /// last time the pending swap was broadcast
+    pub last_sent: Option<DateTime<Utc>>,
+    /// fee bumped rebuilds of the pending swap
+    #[serde(default)]
+    pub retries: u32,
+    /// a sell failed after every retry, tokens are still held and need a manual sell
+    #[serde(default)]
+    pub needs_attention: bool,
+    /// every state change in order, see `Trade::transition`
+    #[serde(default)]
+    pub state_history: Vec<TradeStateEvent>,
+    /// set by `Trade::load_mint` before the first swap
+    #[serde(default)]
+    pub mint_info: Option<MintInfo>,
+    /// sells signed ahead against the wallet's durable nonce, see `Trade::presign_exits`
+    #[serde(skip)]
+    pub presigned_exits: Vec<PresignedExit>,
+    /// price a presigned sell fires at, its min out is the quote scaled to this price
+    #[serde(skip)]
+    pub sell_trigger_price: Option<Decimal>,
+    /// nonce account of a presigned sell that went out, the next rebuilt sell advances it so the old one can't land
+    #[serde(default)]
+    pub nonce_in_flight: Option<Pubkey>,
+    /// the pump curve completed and the coin moves to Raydium or PumpSwap, swaps wait for the new pool
+    #[serde(default)]
+    pub migrating: bool,
+    /// SOL the pending scale-in buy spends, the first buy spends `cfg.max_sol`
+    #[serde(default)]
+    pub scale_in_sol: Option<Decimal>,
     pub instructions: Vec<TradeInstruction>,
     pub transactions: Vec<TradeTransaction>,
     pub rpc_status: RpcState,
@@ -98,6 +155,89 @@ pub struct RayAmm4{
     pub token_program_id: Pubkey,
 }
 
+/// Raydium CP-Swap pool, mints are sorted so token 0 is the smaller key
+#[derive(Clone,Copy,Serialize,Deserialize,Debug,JsonSchema)]
+pub struct RayCpmm{
+    #[schemars(with = "String")]
+    pub pool:Pubkey,
+    #[schemars(with = "String")]
+    pub amm_config:Pubkey,
+    #[schemars(with = "String")]
+    pub observation:Pubkey,
+    #[schemars(with = "String")]
+    pub token_0_mint:Pubkey,
+    #[schemars(with = "String")]
+    pub token_1_mint:Pubkey,
+    #[schemars(with = "String")]
+    pub token_0_vault:Pubkey,
+    #[schemars(with = "String")]
+    pub token_1_vault:Pubkey,
+    #[schemars(with = "String")]
+    pub token_0_program:Pubkey,
+    #[schemars(with = "String")]
+    pub token_1_program:Pubkey,
+    /// decimal places of asset, set by `Trade::load_pool`
+    pub decimals: Option<i16>,
+    /// trade fee in millionths, set by `Trade::load_pool`
+    pub trade_fee_rate: Option<u64>,
+}
+
+/// Raydium concentrated liquidity pool, mints are sorted so token 0 is the smaller key
+#[derive(Clone,Copy,Serialize,Deserialize,Debug,JsonSchema)]
+pub struct RayClmm{
+    #[schemars(with = "String")]
+    pub pool:Pubkey,
+    #[schemars(with = "String")]
+    pub amm_config:Pubkey,
+    #[schemars(with = "String")]
+    pub observation:Pubkey,
+    #[schemars(with = "String")]
+    pub token_0_mint:Pubkey,
+    #[schemars(with = "String")]
+    pub token_1_mint:Pubkey,
+    #[schemars(with = "String")]
+    pub token_0_vault:Pubkey,
+    #[schemars(with = "String")]
+    pub token_1_vault:Pubkey,
+    /// decimal places of asset, set by `Trade::load_pool`
+    pub decimals: Option<i16>,
+    /// trade fee in millionths, set by `Trade::load_pool`
+    pub trade_fee_rate: Option<u64>,
+    /// set by `Trade::load_pool`
+    pub tick_spacing: Option<u16>,
+    /// tick after the last swap, picks the tick arrays of our swap
+    pub tick_current: Option<i32>,
+    /// initialized tick arrays, read again by `Trade::load_pool` before every send
+    #[serde(default)]
+    pub tick_array_bitmap: Option<[u64; 16]>,
+}
+
+/// PumpSwap pool, where pump.fun coins graduate to. Base is the coin, quote is SOL.
+#[derive(Clone,Copy,Serialize,Deserialize,Debug,JsonSchema)]
+pub struct PumpAmm{
+    #[schemars(with = "String")]
+    pub pool:Pubkey,
+    #[schemars(with = "String")]
+    pub base_mint:Pubkey,
+    #[schemars(with = "String")]
+    pub pool_base_token_account:Pubkey,
+    #[schemars(with = "String")]
+    pub pool_quote_token_account:Pubkey,
+    /// spl-token or Token-2022, whichever owns the base mint
+    #[schemars(with = "String")]
+    pub base_token_program:Pubkey,
+    /// any of the global config's recipients, taken from the observed swap
+    #[schemars(with = "String")]
+    pub protocol_fee_recipient:Pubkey,
+    /// set by the observed swap or `Trade::load_pool`
+    #[schemars(with = "Option<String>")]
+    pub coin_creator_vault_authority:Option<Pubkey>,
+    /// decimal places of asset, set by `Trade::load_pool`
+    pub decimals: Option<i16>,
+    /// lp, protocol and creator fee of the last swap event
+    pub fee_bps: Option<u64>,
+}
+
 /// Mint information, used to determine what mint to buy or sell
 #[derive(Debug, Serialize, Deserialize, Clone, Copy, JsonSchema)]
 pub enum TradePool {
@@ -111,10 +251,30 @@ pub enum TradePool {
     #[schemars(skip)]
     /// Pump bonding curve object
     PumpBondingCurve(PumpBondingCurve),
+    #[schemars(skip)]
+    /// Raydium CP-Swap object
+    RayCpmm(RayCpmm),
+    #[schemars(skip)]
+    /// Raydium CLMM object
+    RayClmm(RayClmm),
+    #[schemars(skip)]
+    /// PumpSwap object
+    PumpAmm(PumpAmm),
 }
 
 
 
+/// Limit buy, fills on the first log where any set condition holds
+#[derive(Clone,Copy,Serialize,Deserialize,Debug,JsonSchema)]
+pub struct LimitOrder {
+    /// buy at or below this price in SOL per token
+    pub price: Option<Decimal>,
+    /// buy once pool tvl in SOL reaches this
+    pub tvl: Option<Decimal>,
+    /// cancel the order after this time
+    pub expiry: DateTime<Utc>,
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug)]
 #[serde(tag = "name", content = "arguments")]
 pub enum TradeRequest2 {
@@ -194,13 +354,30 @@ pub struct NewTrade {
 #[derive(Clone,Serialize,Deserialize,Debug)]
 pub enum TradeInstruction {
     Jito(Vec<Instruction>),
-    General(Vec<Instruction>)
+    General(Vec<Instruction>),
+    /// tip transfer sent as its own transaction in a jito bundle
+    JitoTip(Vec<Instruction>),
 }
 
 #[derive(Clone,Serialize,Deserialize,Debug, PartialEq)]
 pub enum TradeTransaction {
-    Jito(Transaction),
-    General(Transaction)
+    Jito(#[serde(deserialize_with = "versioned_or_legacy")] VersionedTransaction),
+    General(#[serde(deserialize_with = "versioned_or_legacy")] VersionedTransaction),
+    JitoTip(#[serde(deserialize_with = "versioned_or_legacy")] VersionedTransaction),
+}
+
+/// Reads transactions stored before v0 support as well
+fn versioned_or_legacy<'de, D: Deserializer<'de>>(d: D) -> Result<VersionedTransaction, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum AnyTransaction {
+        Versioned(VersionedTransaction),
+        Legacy(Transaction),
+    }
+    Ok(match AnyTransaction::deserialize(d)? {
+        AnyTransaction::Versioned(x) => x,
+        AnyTransaction::Legacy(x) => x.into(),
+    })
 }
 
 #[repr(i32)]
@@ -220,7 +397,64 @@ pub enum TradeState {
     /// trade has been placed and buy was failed
     BuyFailed,
     /// trade has been placed and sell was failed
-    SellFailed
+    SellFailed,
+    /// part of the position has been sold, the rest is still open
+    PartiallyClosed,
+}
+
+#[derive(Clone,Copy,Debug,Serialize,Deserialize,PartialEq,Eq)]
+pub enum TradeFillSide {
+    Buy,
+    Sell,
+}
+
+/// A landed swap on a trade, a position can have many buys and sells
+#[derive(Clone,Debug,Serialize,Deserialize,PartialEq)]
+pub struct TradeFill {
+    pub side: TradeFillSide,
+    pub signature: String,
+    pub time: DateTime<Utc>,
+    /// token amount in the mint's smallest unit
+    pub amount: Decimal,
+    /// SOL per token
+    pub price: Decimal,
+    /// tokens withheld by a Token-2022 transfer fee, on top of `amount` for buys and out of it for sells
+    #[serde(default)]
+    pub transfer_fee: Decimal,
+}
+
+/// A sell signed ahead against the wallet's durable nonce
+#[derive(Clone,Debug)]
+pub struct PresignedExit {
+    /// `Trade::sell_amount` it sells, none closes the position
+    pub sell_amount: Option<u64>,
+    /// tokens held when it was signed, it is stale once that changes
+    pub amount: Decimal,
+    /// `Trade::high_price` when it was signed, a higher one tightens the trailing stop
+    pub high_price: Option<Decimal>,
+    pub nonce: Pubkey,
+    pub nonce_hash: Hash,
+    pub instructions: Vec<TradeInstruction>,
+    pub transactions: Vec<TradeTransaction>,
+}
+
+/// Owner program of a traded mint, Token-2022 mints keep the raw account for their extensions
+#[derive(Clone,Debug,Serialize,Deserialize,PartialEq)]
+pub struct MintInfo {
+    pub program: Pubkey,
+    /// mint account data, empty for spl-token mints
+    pub data: Vec<u8>,
+    /// epoch the mint was loaded at, the fee is read at the later of this and the current epoch
+    pub epoch: u64,
+}
+
+/// A state change on a trade
+#[derive(Clone,Debug,Serialize,Deserialize,PartialEq)]
+pub struct TradeStateEvent {
+    pub time: DateTime<Utc>,
+    pub from: TradeState,
+    pub to: TradeState,
+    pub reason: String,
 }
 
 
@@ -268,7 +502,15 @@ pub struct TipStatistics {
 #[derive(Clone,Debug,Serialize,Deserialize,PartialEq,Eq)]
 pub struct TradeRpcLogJito {
     pub general:TradeRpcLogGeneral,
-    pub tip_sol_ui: String
+    pub tip_sol_ui: String,
+    #[serde(default)]
+    pub tip_policy: JitoTipPolicy,
+    /// set when the swap went out as a bundle
+    #[serde(default)]
+    pub bundle_id: Option<String>,
+    /// last `confirmation_status` from the bundle status api, or the bundle error
+    #[serde(default)]
+    pub bundle_status: Option<String>,
 }
 
 #[derive(Clone,Debug,Serialize,Deserialize,PartialEq,Eq)]
@@ -276,7 +518,8 @@ pub struct TradeRpcLogGeneral {
     pub signature:String,
     pub response_time:String,
     pub name:String,
-    pub tx: Transaction,
+    #[serde(deserialize_with = "versioned_or_legacy")]
+    pub tx: VersionedTransaction,
     pub ix: Vec<Instruction>,
 }
 
@@ -322,6 +565,10 @@ pub enum TradeRpc {
     General {
         rpc: RpcClient,
         info: RpcInfo
+    },
+    /// nothing is broadcast, see `Trade::paper_amount_out`
+    Paper {
+        info: RpcInfo
     }
 }
 
@@ -343,4 +590,63 @@ pub struct BuyPrompt {
     pub tp: Decimal,
     /// Stop loss percent, should be negative number
     pub sl: Decimal,
+    /// Strategy name, uses the user's strategy when empty
+    pub strategy: Option<String>,
+    /// Wait for a price or tvl before buying
+    pub limit: Option<LimitOrder>,
+    /// Simulate the trade without sending transactions
+    pub paper: Option<bool>,
+    /// Trailing stop distance from the highest price e.g 0.1
+    pub trailing_sl: Option<Decimal>,
+    /// Take profit levels, each sells a fraction of the tokens still held
+    pub tp_ladder: Option<Vec<TakeProfitLevel>>,
+    /// Jito tip sizing, fixed share of the fee budget when empty
+    pub jito_tip_policy: Option<JitoTipPolicy>,
+    /// Send swap and tip as a jito bundle, no tip is paid if the swap fails
+    pub jito_bundle: Option<bool>,
+    /// Simulate every swap first and drop it if it would fail
+    pub simulate: Option<bool>,
+    /// Send v0 transactions that use the wallet's address lookup table
+    pub versioned: Option<bool>,
+    /// Sign stop loss and take profit sells ahead so they go out instantly
+    pub durable_nonce: Option<bool>,
+    /// Wallet to buy with, the user's `wallet_selection` when empty
+    pub wallet: Option<WalletSelection>,
+    /// Split `sol_ui` evenly into one trade per wallet over this many wallets
+    pub split: Option<usize>,
+}
+
+/// Sell part of an open trade
+#[derive(JsonSchema, Deserialize, Serialize, Clone,Debug)]
+pub struct SellPrompt {
+    /// fraction of the tokens held e.g 0.5, 1 closes the trade
+    #[serde(alias = "pct")]
+    pub fraction: Decimal,
+    /// id of the user's pending buy to bundle the sell behind, both land in the same block or neither does
+    pub behind: Option<i64>,
+}
+
+/// Move SOL between two of the user's wallets
+#[derive(JsonSchema, Deserialize, Serialize, Clone,Debug)]
+pub struct FundPrompt {
+    /// sending wallet pubkey
+    pub from: String,
+    /// receiving wallet pubkey
+    pub to: String,
+    /// SOL amount
+    pub sol_ui: Decimal,
+}
+
+/// Collect SOL from the user's idle wallets
+#[derive(JsonSchema, Deserialize, Serialize, Clone,Debug)]
+pub struct SweepPrompt {
+    /// receiving wallet pubkey
+    pub to: String,
+}
+
+/// Add to an open trade at current prices
+#[derive(JsonSchema, Deserialize, Serialize, Clone,Debug)]
+pub struct AddPrompt {
+    /// SOL amount
+    pub sol_ui: Decimal,
 }
@@ -1,14 +1,19 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use solana_sdk::signature::Keypair;
 use tokio::sync::oneshot::Sender;
 use raydium_amm::solana_program::pubkey::Pubkey;
 use crate::trade_cmd::*;
 use crate::trade::*;
+use crate::constant::SOLANA_MINT;
 
 pub struct Cache {
     pub trades: HashMap<i64, Trade>,
     pub mints: HashMap<Pubkey, HashMap<i64,Trade>>,
     pub oneshots: HashMap<i64, tokio::sync::oneshot::Sender<InternalCommand>>,
+    /// latest stats from `jito_chan`
+    pub jito_tip: Option<TipStatistics>,
+    /// rpcs that haven't failed the trade's current swap yet, see `trade_chan::swap`
+    pub sends_left: HashMap<i64, usize>,
 }
 
 impl Cache {
@@ -20,6 +25,7 @@ impl Cache {
             .insert(trade.id,trade.clone());
     }
     pub fn remove(&mut self, trade: &Trade) {
+        self.sends_left.remove(&trade.id);
         if let Some(trade) = self.trades.remove(&trade.id) {
             self.mints.entry(trade.mint()).and_modify(|x| {
                 x.remove(&trade.id);
@@ -42,7 +48,26 @@ impl Cache {
             .get(&mint)
             .and_then(|x| Some(x.values().collect::<Vec<_>>()))
     }
+    /// Answers whoever is waiting on the trade with `Trade::swap_later`
+    pub fn reply(&mut self, trade: &Trade) {
+        if let Some(s) = self.oneshots.remove(&trade.id) {
+            let _ = s.send(InternalCommand::TradeResponse(trade.clone()));
+        }
+    }
     pub fn values(&self) -> Vec<Trade> {
         self.trades.values().cloned().collect()
     }
+    /// Mints and pools of the cached trades, what the geyser subscription has to cover
+    pub fn watched_accounts(&self) -> BTreeSet<Pubkey> {
+        let mut accounts = BTreeSet::new();
+        for trade in self.trades.values() {
+            accounts.insert(trade.mint());
+            if trade.has_pool() {
+                accounts.insert(trade.amm());
+            }
+        }
+        // the root trade, every swap touches it
+        accounts.remove(&SOLANA_MINT);
+        accounts
+    }
 }
@@ -1,6 +1,11 @@
+use crate::backtest::Recorder;
+use crate::blockhash::block_height;
 use crate::chan::Chan;
 use crate::constant::*;
+use crate::geyser::geyser_stream;
 use crate::plog::*;
+use crate::pump_amm::pump_amm_canonical_pool;
+use crate::strategy::*;
 use crate::swap_config::*;
 use crate::trade::*;
 use crate::trade_cache::*;
@@ -20,12 +25,69 @@ use solana_sdk::signature::{Keypair, Signature};
 use std::collections::HashMap;
 use std::time::Duration;
 use tokio::sync::mpsc::Receiver;
-use tokio::time::Instant;
+use tokio::sync::watch;
 use yellowstone_grpc_proto::geyser::{CommitmentLevel, SubscribeRequest, SubscribeRequestFilterTransactions, SubscribeUpdate, SubscribeUpdateTransaction, SubscribeUpdateTransactionInfo};
 use yellowstone_grpc_proto::geyser::subscribe_update::UpdateOneof;
 use yellowstone_grpc_proto::prelude::{Message, Transaction, TransactionStatusMeta};
 use yellowstone_grpc_proto::tonic::Status;
 
+/// base64 prefixes of the pump trade, CP-Swap/CLMM swap and PumpSwap buy/sell events
+const SWAP_EVENT_MARKERS: [&str; 4] = ["vdt/007mYe", "QMbN6CYI", "Z/RSHyz1", "Pi83CqUD"];
+
+/// Curve trades and migrations, plus every swap touching a held mint or pool
+fn subscribe_request(db: &Cache) -> SubscribeRequest {
+    let filter = |accounts: Vec<String>, required: bool| {
+        let (account_include, account_required) = if required { (vec![], accounts) } else { (accounts, vec![]) };
+        SubscribeRequestFilterTransactions {
+            account_include,
+            account_required,
+            failed: Some(false),
+            vote: Some(false),
+            ..Default::default()
+        }
+    };
+    let mut transactions = HashMap::from([
+        ("client".to_string(), filter(vec![PUMP_FEE.to_string()], true)),
+        // pools created for completed curves
+        ("migration".to_string(), filter(vec![PUMP_MIGRATION.to_string()], true)),
+    ]);
+    let held = db.watched_accounts().iter().map(|x| x.to_string()).collect::<Vec<_>>();
+    // an empty include list would match every transaction
+    if !held.is_empty() {
+        transactions.insert("held".to_string(), filter(held, false));
+    }
+    SubscribeRequest {
+        commitment: Some(i32::from(CommitmentLevel::Processed)),
+        transactions,
+        ..Default::default()
+    }
+}
+
+/// Sends the geyser endpoints a new request when the held mints or pools changed
+fn resubscribe(subscribe: &watch::Sender<SubscribeRequest>, db: &Cache) {
+    let request = subscribe_request(db);
+    subscribe.send_if_modified(|x| {
+        if *x == request {
+            return false;
+        }
+        info!("resubscribing to {} accounts", db.watched_accounts().len());
+        *x = request;
+        true
+    });
+}
+
+/// blocks a presigned sell gets before it is rebuilt, about as long as a blockhash lives
+const NONCE_EXIT_BLOCKS: u64 = 150;
+
+/// What woke up the engine loop
+enum EngineEvent {
+    Command(InternalCommand),
+    Update(SubscribeUpdate),
+    Heartbeat,
+    /// once a second, for pending swaps
+    Tick,
+}
+
 pub struct Engine {
     pub red: ConnectionManager,
     pub kafka: FutureProducer,
@@ -35,19 +97,30 @@ pub struct Engine {
 }
 
 pub fn swap(chan: Chan, mut trade: Trade, cache: &mut Cache) {
-    trade.update_rpc_status(RpcState::Busy);
-    if trade.state == TradeState::Buy {
-        trade.state = TradeState::PendingBuy;
-    } else {
-        trade.state = TradeState::PendingSell;
+    let (to, reason) = match (trade.is_buy(), trade.is_pending()) {
+        (true, false) => (TradeState::PendingBuy, "buy sent"),
+        (true, true) => (TradeState::PendingBuy, "buy resent"),
+        (false, false) => (TradeState::PendingSell, "sell sent"),
+        (false, true) => (TradeState::PendingSell, "sell resent"),
+    };
+    if let Err(e) = trade.transition(to, reason) {
+        error!("{e}");
+        return;
     }
+    trade.update_rpc_status(RpcState::Busy);
+    trade.jito_tip = cache.jito_tip;
+    trade.last_sent = Some(Utc::now());
+    // the old send's height, the tick would expire the new one with it
+    trade.last_valid_block_height = None;
     cache.upsert(&trade);
     trade = trade.rebuild_instructions();
 
-    let rpcs_config = RpcsConfig { jito_tip: None };
+    let rpcs_config = RpcsConfig { jito_tip: cache.jito_tip };
+    let rpcs = rpcs_for(&trade, rpcs_config);
+    cache.sends_left.insert(trade.id, rpcs.len());
     tokio::spawn({
         async move {
-            let mut r = trade.send_many(rpcs(rpcs_config)).await;
+            let mut r = trade.send_many(rpcs).await;
             while let Some(Ok(res)) = r.next().await {
                 chan.trade
                     .try_send(InternalCommand::RpcTradeResponse(res))
@@ -57,6 +130,106 @@ pub fn swap(chan: Chan, mut trade: Trade, cache: &mut Cache) {
     });
 }
 
+/// Sends a sell presigned by `Trade::presign_exits` as is, no blockhash or signing on the way
+pub fn swap_presigned(chan: Chan, mut trade: Trade, exit: PresignedExit, cache: &mut Cache) {
+    if let Err(e) = trade.transition(TradeState::PendingSell, "presigned sell sent") {
+        error!("{e}");
+        return;
+    }
+    trade.update_rpc_status(RpcState::Busy);
+    trade.jito_tip = cache.jito_tip;
+    trade.last_sent = Some(Utc::now());
+    // the nonce never expires, give it as long as a blockhash before rebuilding
+    trade.last_valid_block_height = Some(block_height() + NONCE_EXIT_BLOCKS);
+    trade.nonce_in_flight = Some(exit.nonce);
+    trade.instructions = exit.instructions;
+    trade.transactions = exit.transactions;
+    trade.presigned_exits.clear();
+    cache.upsert(&trade);
+
+    let rpcs = rpcs_for(&trade, RpcsConfig { jito_tip: cache.jito_tip });
+    cache.sends_left.insert(trade.id, rpcs.len());
+    let mut r = trade.broadcast(rpcs);
+    tokio::spawn(async move {
+        while let Some(Ok(res)) = r.next().await {
+            chan.trade
+                .try_send(InternalCommand::RpcTradeResponse(res))
+                .unwrap();
+        }
+    });
+}
+
+/// Signs the trade's exits in the background, they come back as `InternalCommand::PresignedExits`
+pub fn presign(chan: Chan, trade: &Trade) {
+    if !trade.cfg.durable_nonce || trade.paper || !trade.is_open() {
+        return;
+    }
+    let trade = trade.clone();
+    tokio::spawn(async move {
+        match trade.presign_exits().await {
+            Ok(exits) => chan
+                .trade
+                .try_send(InternalCommand::PresignedExits { trade_id: trade.id, exits })
+                .unwrap(),
+            Err(e) => error!("presign {} {e}", trade.id),
+        }
+    });
+}
+
+/// Settles a swap that failed on every rpc, see `Trade::fail_swap`.
+/// Failed buys leave the cache, failed sells stay for a retry or a manual sell.
+pub fn swap_failed(chan: Chan, mut trade: Trade, cache: &mut Cache) {
+    if let Err(e) = trade.fail_swap() {
+        error!("{e}");
+        return;
+    }
+    cache.sends_left.remove(&trade.id);
+    if trade.state == TradeState::BuyFailed {
+        cache.remove(&trade);
+    } else {
+        cache.upsert(&trade);
+    }
+    if trade.needs_attention {
+        error!("trade {} needs attention, sell failed after {} retries", trade.id, trade.retries);
+    }
+    cache.reply(&trade);
+    chan.bg.try_send(InternalCommand::TradeConfirmation(trade)).unwrap();
+}
+
+/// Cancels a limit buy past its expiry, whoever waits on it gets the failed trade
+pub fn expire_limit(mut trade: Trade, cache: &mut Cache) {
+    info!("limit expired {}", trade.id);
+    trade.error = Some("limit order expired".to_string());
+    if let Err(e) = trade.transition(TradeState::BuyFailed, "limit order expired") {
+        error!("{e}");
+    }
+    cache.remove(&trade);
+    cache.reply(&trade);
+}
+
+/// Looks up the signatures of a buy whose blockhash expired before it is sent again, see `Trade::reconcile`
+pub fn check_expired_buy(chan: Chan, trade: Trade) {
+    tokio::spawn(async move {
+        let trade = trade.reconcile().await;
+        chan.trade
+            .try_send(InternalCommand::ExpiredBuyChecked(trade))
+            .unwrap();
+    });
+}
+
+/// Sends the pending swap's transactions again, see `Trade::rebroadcast_due`
+pub fn rebroadcast(chan: Chan, trade: &Trade) {
+    let rpcs = rpcs_for(trade, RpcsConfig { jito_tip: trade.jito_tip });
+    let mut r = trade.broadcast(rpcs);
+    tokio::spawn(async move {
+        while let Some(Ok(res)) = r.next().await {
+            chan.trade
+                .try_send(InternalCommand::RebroadcastResponse(res))
+                .unwrap();
+        }
+    });
+}
+
 /// example of swap_base_in log
 /// https://solscan.io/token/4xDVi6XiDU6rAdvm4VjAdMoaXACXVjBuzLS74Cw1uvA3?activity_type=ACTIVITY_SPL_INIT_MINT&activity_type=ACTIVITY_TOKEN_ADD_LIQ&activity_type=ACTIVITY_TOKEN_REMOVE_LIQ&activity_type=ACTIVITY_TOKEN_SWAP&time=1735257600000&time=1735321439000&page=6#defiactivities
 /// SwapBaseInLog { log_type: 3, amount_in: 670000000000, minimum_out: 1, direction: 1, user_source: 670000000000, pool_coin: 9000000000000000000, pool_pc: 150000000, out_amount: 8997980477953550992 }
@@ -65,17 +238,30 @@ pub fn swap(chan: Chan, mut trade: Trade, cache: &mut Cache) {
 /// pool swap - https://solscan.io/tx/SggjhnEzULzofBb6njNaaFP7T31z6uddrx2ibafA6FhpQxuifEoYh2WiRWgg5geysqRkiAuhS7esgDMxLxtmTp5
 /// SwapBaseOutLog { log_type: 4, max_in: 2955714285, amount_out: 2364571428, direction: 1, user_source: 10000000, pool_coin: 171880473738872568, pool_pc: 843000100000, deduct_in: 11628 }
 pub async fn trade_chan(chan: Chan, mut rec: Receiver<InternalCommand>) {
-    let mut heartbeat = Instant::now();
     let m = mongo().await;
     let red = redis_pool().await;
     let k = kakfa_producer().await;
     let c = m.collection::<Trade>("trades");
     let max_positions = 1;
     let mut price_id = 0;
-    let trades = Trade::db_get_active_trades(&c)
-        .await
-        .iter()
-        .map(|x| (x.id.clone(), x.clone()))
+    // swaps may have landed while the engine was down
+    let trades = futures::future::join_all(
+        Trade::db_get_active_trades(&c)
+            .await
+            .into_iter()
+            .map(|x| x.reconcile()),
+    )
+    .await;
+    for x in trades.iter() {
+        chan.bg
+            .send(InternalCommand::TradeConfirmation(x.clone()))
+            .await
+            .unwrap();
+    }
+    let trades = trades
+        .into_iter()
+        .filter(|x| !matches!(x.state, TradeState::BuyFailed | TradeState::SellSuccess))
+        .map(|x| (x.id.clone(), x))
         .collect::<HashMap<_, _>>();
 
     let mut mints = HashMap::new();
@@ -89,6 +275,8 @@ pub async fn trade_chan(chan: Chan, mut rec: Receiver<InternalCommand>) {
         trades,
         mints,
         oneshots: Default::default(),
+        jito_tip: None,
+        sends_left: Default::default(),
     };
 
     let cfg = PositionConfig {
@@ -109,93 +297,277 @@ pub async fn trade_chan(chan: Chan, mut rec: Receiver<InternalCommand>) {
         tp: dec!(0.1),
         sl: dec!(-0.01),
         fee_pct: dec!(0.2),
+        trailing_sl: dec!(0),
+        tp_ladder: Default::default(),
+        jito_tip_policy: Default::default(),
+        jito_bundle: false,
+        simulate: false,
+        cu_margin: dec!(0.2),
+        rebroadcast_ms: 2_000,
+        max_retries: 2,
+        fee_bump: dec!(0.5),
+        versioned: false,
+        durable_nonce: false,
+        ray_clmm_gas_limit: 200_000,
     };
-    let geyser = geyser();
+    let strategies = Strategies::default();
 
     // let mut ignore_mints = get_ignore_mints().await;
-    let mut c = geyser.connect().await.unwrap();
-    info!("connected to geyser");
-    tokio::time::sleep(Duration::from_secs(5)).await;
-    let r = SubscribeRequest {
-        commitment: Some(i32::from(CommitmentLevel::Processed)),
-        transactions: vec![(
-            "client".to_string(),
-            SubscribeRequestFilterTransactions {
-                account_required: vec![PUMP_FEE.to_string()],
-                failed: Some(false),
-                vote: Some(false),
-                ..Default::default()
-            },
-        )]
-        .into_iter()
-        .collect(),
-        ..Default::default()
-    };
-    let mut rpcs_config = RpcsConfig { jito_tip: None };
-    let mut rpc_len = rpcs(rpcs_config).len();
     // let mut rpc_state = RpcState::Free;
-    let (_subscribe_tx, mut stream) = c.subscribe_with_request(Some(r.clone())).await.unwrap();
-
-    while let Some(message) = stream.next().await {
-        if heartbeat.elapsed().as_secs() > 60 {
-            info!("heartbeat {:?}", heartbeat.elapsed());
-            heartbeat = Instant::now();
+    // reconnects and resubscribes on its own, and again whenever the held mints or pools change
+    let (subscribe, subscribe_rx) = watch::channel(subscribe_request(&db));
+    let mut stream = geyser_stream(subscribe_rx);
+    // a bad record path only costs the recording
+    let mut recorder = GEYSER_RECORD_PATH.and_then(|x| match Recorder::create(x) {
+        Ok(recorder) => Some(recorder),
+        Err(e) => {
+            error!("recorder {x} {e}");
+            None
         }
+    });
 
-        if let Ok(cmd) = rec.try_recv() {
-            match cmd {
-                InternalCommand::SwapRequest(trade) => {
-                    swap(chan.clone(), trade, &mut db);
-                }
-                InternalCommand::SwapLimitRequest(trade, _) => {
-                    db.upsert(&trade);
+    let mut heartbeat = tokio::time::interval(Duration::from_secs(60));
+    let mut tick = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        // commands first, a busy stream must not hold back /buy or rpc responses
+        let event = tokio::select! {
+            biased;
+            Some(cmd) = rec.recv() => EngineEvent::Command(cmd),
+            Some(update) = stream.recv() => EngineEvent::Update(update),
+            _ = heartbeat.tick() => EngineEvent::Heartbeat,
+            _ = tick.tick() => EngineEvent::Tick,
+        };
+        let update = match event {
+            EngineEvent::Heartbeat => {
+                info!("heartbeat {} trades", db.trades.len());
+                continue;
+            }
+            EngineEvent::Tick => {
+                let height = block_height();
+                let now = Utc::now();
+                for mut trade in db.values() {
+                    if trade.blockhash_expired(height) && trade.state == TradeState::PendingBuy {
+                        // the buy may have landed without the stream seeing it, buying again would double the position
+                        trade.last_valid_block_height = None;
+                        db.upsert(&trade);
+                        check_expired_buy(chan.clone(), trade);
+                    } else if trade.blockhash_expired(height) && trade.retries < trade.cfg.max_retries {
+                        // the old transaction can't land anymore, rebuild with a fresh hash and a higher fee
+                        trade.retries += 1;
+                        warn!("blockhash expired {} {:?}, retry {}", trade.id, trade.state, trade.retries);
+                        swap(chan.clone(), trade, &mut db);
+                    } else if trade.blockhash_expired(height) {
+                        warn!("blockhash expired {} {:?}, giving up", trade.id, trade.state);
+                        trade.error = Some(format!("blockhash expired after {} retries", trade.retries));
+                        swap_failed(chan.clone(), trade, &mut db);
+                    } else if trade.rebroadcast_due(now) {
+                        trade.last_sent = Some(now);
+                        db.upsert(&trade);
+                        rebroadcast(chan.clone(), &trade);
+                    } else if trade.sell_retry_due() {
+                        trade.retries += 1;
+                        warn!("retrying failed sell {}, retry {}", trade.id, trade.retries);
+                        swap(chan.clone(), trade, &mut db);
+                    } else if trade.state == TradeState::Buy && trade.limit_expired() {
+                        // a quiet mint has no logs to expire it on
+                        expire_limit(trade, &mut db);
+                    }
                 }
-                InternalCommand::RpcTradeResponse(res) => match res {
-                    Ok(mut updated_trade) => {
-                        let mut old_trade = db.get_by_trade(&updated_trade).cloned().unwrap();
-                        old_trade.extend_rpc_logs(&updated_trade.rpc_logs);
-                        let signature = updated_trade.signature_unchecked();
-
-                        db.upsert(&updated_trade);
-
-                        poll_trade_and_emit(
-                            signature,
-                            chan.clone(),
-                            updated_trade.clone(),
-                            "position".to_string(),
-                            |chan, mut trade, error| {
-                                trade.error = Some(error.unwrap().to_string());
-                                chan.trade
-                                    .try_send(InternalCommand::RpcTradeResponse(Err(trade)))
+                // migrations give trades their new pools
+                resubscribe(&subscribe, &db);
+                continue;
+            }
+            EngineEvent::Command(cmd) => {
+                match cmd {
+                    InternalCommand::SwapRequest(trade) => {
+                        swap(chan.clone(), trade, &mut db);
+                    }
+                    InternalCommand::JitoTip(tip) => {
+                        db.jito_tip = Some(tip);
+                    }
+                    InternalCommand::BundleStatus { trade_id, bundle_id, status } => {
+                        if let Some(mut trade) = db.get_by_id(trade_id).cloned() {
+                            info!("bundle {bundle_id} {status}");
+                            trade.rpc_logs.iter_mut().for_each(|x| x.set_bundle_status(&bundle_id, status.clone()));
+                            db.upsert(&trade);
+                        }
+                    }
+                    InternalCommand::PresignedExits { trade_id, exits } => {
+                        // the position may have moved while signing
+                        let trade = db
+                            .get_by_id(trade_id)
+                            .filter(|x| x.is_open() && exits.iter().all(|e| e.amount == x.amount))
+                            // exits signed for an older high finished after the newer ones
+                            .filter(|x| x.presigned_exits.iter().all(|p| exits.iter().all(|e| e.high_price >= p.high_price)))
+                            .cloned();
+                        if let Some(mut trade) = trade {
+                            info!("presigned {} exits for {trade_id}", exits.len());
+                            trade.presigned_exits = exits;
+                            db.upsert(&trade);
+                        }
+                    }
+                    InternalCommand::SwapLimitRequest(trade, s) => {
+                        db.oneshots.insert(trade.id, s);
+                        db.upsert(&trade);
+                    }
+                    InternalCommand::CachedTrades(s) => {
+                        let _ = s.send(InternalCommand::CachedTradesResponse(db.values()));
+                    }
+                    InternalCommand::ScaleOutRequest(id, fraction, behind) => {
+                        // a manual sell also takes over a sell that gave up
+                        let trade = db
+                            .get_by_id(id)
+                            .filter(|x| x.is_open() || x.state == TradeState::SellFailed)
+                            .cloned();
+                        if let Some(mut trade) = trade {
+                            trade.needs_attention = false;
+                            trade.retries = 0;
+                            let mut trade = match trade.with_sell_fraction(fraction) {
+                                Ok(x) => x,
+                                Err(e) => {
+                                    error!("{e}");
+                                    continue;
+                                }
+                            };
+                            if let Some(behind) = behind {
+                                match db.get_by_id(behind).filter(|x| x.state == TradeState::PendingBuy) {
+                                    Some(buy) => trade = trade.with_bundle_behind(buy),
+                                    None => warn!("buy {behind} not pending, sell {id} goes out alone"),
+                                }
+                            }
+                            swap(chan.clone(), trade, &mut db);
+                        }
+                    }
+                    InternalCommand::ScaleInRequest(id, sol) => {
+                        if let Some(mut trade) = db.get_by_id(id).filter(|x| x.is_open()).cloned() {
+                            trade.scale_in_sol = Some(sol);
+                            if let Err(e) = trade.transition(TradeState::Buy, "scale in") {
+                                error!("{e}");
+                                continue;
+                            }
+                            swap(chan.clone(), trade, &mut db);
+                        }
+                    }
+                    InternalCommand::ExpiredBuyChecked(checked) => {
+                        // the stream may have seen the fill meanwhile
+                        let Some(mut trade) = db
+                            .get_by_trade(&checked)
+                            .filter(|x| x.state == TradeState::PendingBuy)
+                            .cloned()
+                        else {
+                            continue;
+                        };
+                        match checked.state {
+                            TradeState::BuySuccess | TradeState::PartiallyClosed => {
+                                info!("expired buy {} landed", checked.id);
+                                db.upsert(&checked);
+                                db.reply(&checked);
+                                chan.bg
+                                    .try_send(InternalCommand::TradeConfirmation(checked.clone()))
                                     .unwrap();
-                            },
-                        );
+                                presign(chan.clone(), &checked);
+                            }
+                            TradeState::BuyFailed if trade.retries < trade.cfg.max_retries => {
+                                trade.retries += 1;
+                                warn!("blockhash expired {} {:?}, retry {}", trade.id, trade.state, trade.retries);
+                                swap(chan.clone(), trade, &mut db);
+                            }
+                            TradeState::BuyFailed => {
+                                warn!("blockhash expired {} {:?}, giving up", trade.id, trade.state);
+                                trade.error = Some(format!("blockhash expired after {} retries", trade.retries));
+                                swap_failed(chan.clone(), trade, &mut db);
+                            }
+                            // the lookup failed, the tick checks again
+                            _ => {
+                                trade.last_valid_block_height = Some(0);
+                                db.upsert(&trade);
+                            }
+                        }
                     }
-                    Err(mut update) => {
-                        let mut trade = db.get_by_trade(&update).unwrap().clone();
-                        trade.extend_rpc_logs(&update.rpc_logs);
-                        trade.error = update.error.clone();
-                        db.upsert(&update);
-
-                        // pro version will have this
-                        if trade.rpc_logs.len() == rpc_len {
-                            db.remove(&update);
-                            chan.bg.try_send(InternalCommand::TradeConfirmation(trade)).unwrap();
-                            error!("{:?}", update.console_log());
+                    InternalCommand::RebroadcastResponse(res) => {
+                        let update = match res {
+                            Ok(update) => update,
+                            Err(update) => {
+                                warn!("rebroadcast {} {:?}", update.id, update.error);
+                                continue;
+                            }
+                        };
+                        // the swap may have landed meanwhile
+                        if let Some(mut trade) = db.get_by_trade(&update).filter(|x| x.is_pending()).cloned() {
+                            if let Some(log) = update.rpc_logs.last() {
+                                trade.push_rpc_logs(log.clone());
+                            }
+                            db.upsert(&trade);
                         }
                     }
-                },
-                _ => {}
+                    InternalCommand::RpcTradeResponse(res) => match res {
+                        Ok(updated_trade) => {
+                            // the swap may have landed or failed meanwhile, the send-time copy is stale
+                            let Some(mut trade) = db.get_by_trade(&updated_trade).filter(|x| x.is_pending()).cloned() else {
+                                continue;
+                            };
+                            trade.merge_sent(&updated_trade);
+                            let signature = updated_trade.signature_unchecked();
+
+                            db.upsert(&trade);
+
+                            if let Some(bundle_id) = updated_trade.rpc_logs.last().and_then(|x| x.bundle_id()) {
+                                poll_bundle_and_emit(trade.id, bundle_id.clone(), chan.clone());
+                            }
+                            // paper trades have nothing to poll
+                            if !trade.paper {
+                                poll_trade_and_emit(
+                                    signature,
+                                    chan.clone(),
+                                    trade.clone(),
+                                    "position".to_string(),
+                                    |chan, mut trade, error| {
+                                        trade.error = Some(error.unwrap().to_string());
+                                        chan.trade
+                                            .try_send(InternalCommand::RpcTradeResponse(Err(trade)))
+                                            .unwrap();
+                                    },
+                                );
+                            }
+                        }
+                        Err(update) => {
+                            // the swap may have landed through another rpc
+                            let Some(mut trade) = db.get_by_trade(&update).filter(|x| x.is_pending()).cloned() else {
+                                continue;
+                            };
+                            trade.extend_rpc_logs(&update.rpc_logs);
+                            trade.error = update.error.clone();
+                            let sends_left = db.sends_left.entry(trade.id).or_insert(1);
+                            *sends_left = sends_left.saturating_sub(1);
+                            if *sends_left == 0 {
+                                error!("{:?}", update.console_log());
+                                swap_failed(chan.clone(), trade, &mut db);
+                            } else {
+                                db.upsert(&trade);
+                            }
+                        }
+                    },
+                    _ => {}
+                }
+                resubscribe(&subscribe, &db);
+                continue;
+            }
+            EngineEvent::Update(update) => update,
+        };
+        if let Some(recorder) = recorder.as_mut() {
+            if let Err(e) = recorder.record(&update) {
+                error!("record {e}");
             }
         }
 
-        let commands = decode_tx(message, &db);
+        let commands = decode_tx(Ok(update), &db);
         for cmd in commands {
             match cmd {
                 InternalCommand::TradeUpdate {
                     mut trade,
-                    interested_tx: InterestedTx { signature, .. },
+                    interested_tx,
                 } => {
+                    let signature = interested_tx.signature;
                     if let ProgramLogInfo::RayWithdraw(q) = &trade.plog.log {
                         warn!("{q:?}");
                         continue;
@@ -214,25 +586,14 @@ pub async fn trade_chan(chan: Chan, mut rec: Receiver<InternalCommand>) {
                     match trade.state {
                         TradeState::PendingBuy => {
                             if rpc_signatures.contains(&signature) {
-                                trade.buy_out = Some(trade.plog.amount_out);
                                 // trade.signatures.push(TradeSignature::BuySuccess(signature.to_string()));
-                                trade.buy_time = Some(Utc::now());
-                                trade.buy_price = Some(trade_price.price);
-                                trade.state = TradeState::BuySuccess;
-                                trade.amount = Decimal::from(amount_out);
-
-                                let logs = trade
-                                    .clone()
-                                    .rpc_logs
-                                    .iter_mut()
-                                    .map(|x| {
-                                        x.change_state(signature, TradeRpcLogStatus::Success);
-                                        x.clone()
-                                    })
-                                    .collect::<Vec<_>>();
-                                trade.rpc_logs = logs;
+                                if let Err(e) = trade.apply_buy_fill(signature, amount_out) {
+                                    error!("{e}");
+                                    continue;
+                                }
 
                                 db.upsert(&trade);
+                                db.reply(&trade);
 
                                 chan.bg
                                     .try_send(InternalCommand::TradeConfirmation(trade.clone()))
@@ -242,6 +603,7 @@ pub async fn trade_chan(chan: Chan, mut rec: Receiver<InternalCommand>) {
 
                                 info!("trade buy success");
                                 info!("{}", trade.console_log());
+                                presign(chan.clone(), &trade);
 
                                 // info!(
                                 //     "please look at this! {:?}",
@@ -260,29 +622,60 @@ pub async fn trade_chan(chan: Chan, mut rec: Receiver<InternalCommand>) {
                                 // );
                             }
                         }
-                        TradeState::BuySuccess => {
+                        TradeState::BuySuccess | TradeState::PartiallyClosed => {
                             let buy_price = trade.buy_price.unwrap();
-                            trade.pct = (trade.price - buy_price) / buy_price;
+                            trade.update_pct();
+                            let new_high = trade.update_high_price();
                             db.upsert(&trade);
 
-                            if trade.pct > trade.cfg.tp || trade.pct < trade.cfg.sl {
+                            if let StrategyIntent::Sell(fraction) = strategies.on_log(&trade, &interested_tx, &db) {
                                 info!(
-                                    "buy_price = {buy_price}, price={},   pct={}",
+                                    "buy_price = {buy_price}, price={},   pct={}, fraction={fraction}",
                                     trade.price, trade.pct
                                 );
                                 info!("{}", trade.console_log());
                                 info!("{:?}", &db.get_by_trade(&trade).unwrap());
 
                                 warn!("spamming close");
-                                swap(chan.clone(), trade, &mut db);
+                                trade.sell_tp_level = trade.tp_level_reached();
+                                let trade = match trade.with_sell_fraction(fraction) {
+                                    Ok(x) => x,
+                                    Err(e) => {
+                                        error!("{e}");
+                                        continue;
+                                    }
+                                };
+                                match trade.presigned_exit() {
+                                    Some(exit) => swap_presigned(chan.clone(), trade, exit, &mut db),
+                                    None => swap(chan.clone(), trade, &mut db),
+                                }
+                            } else if new_high && trade.cfg.trailing_sl > Decimal::ZERO {
+                                // the presigned stop has to follow the trailing stop up
+                                presign(chan.clone(), &trade);
                             }
                         }
                         TradeState::PendingSell => {
                             if rpc_signatures.contains(&signature) {
-                                trade.state = TradeState::SellSuccess;
-                                trade.sell_time = Some(Utc::now());
-                                trade.sell_price = Some(trade_price.price);
+                                if let Err(e) = trade.apply_sell_fill(signature) {
+                                    error!("{e}");
+                                    continue;
+                                }
                                 db.upsert(&trade);
+                                // the sell may have advanced the wallet's nonce, sign the open positions again
+                                if trade.cfg.durable_nonce {
+                                    db.values()
+                                        .iter()
+                                        .filter(|x| x.root_kp == trade.root_kp && x.id != trade.id && !x.presigned_exits.is_empty())
+                                        .for_each(|x| presign(chan.clone(), x));
+                                    presign(chan.clone(), &trade);
+                                }
+                                if trade.state == TradeState::PartiallyClosed {
+                                    // keep watching the rest
+                                    chan.bg
+                                        .try_send(InternalCommand::TradeConfirmation(trade.clone()))
+                                        .unwrap();
+                                    info!("trade partial sell success");
+                                }
                                 // chan.bg.try_send(InternalCommand::LogTrade(trade.clone()));
                             }
                         }
@@ -356,25 +749,60 @@ pub async fn trade_chan(chan: Chan, mut rec: Receiver<InternalCommand>) {
                 //         }
                 //     });
                 // }
+                InternalCommand::CurveComplete(id) => {
+                    if let Some(mut trade) = db.get_by_id(id).filter(|x| !x.migrating).cloned() {
+                        warn!("curve complete {} {}, waiting for its pool", trade.id, trade.mint());
+                        trade.migrating = true;
+                        db.upsert(&trade);
+                        chan.bg
+                            .try_send(InternalCommand::TradeConfirmation(trade))
+                            .unwrap();
+                    }
+                }
+                InternalCommand::PoolMigrated { trade_id, interested_tx } => {
+                    let Some(trade) = db.get_by_id(trade_id).cloned() else {
+                        continue;
+                    };
+                    let migrated = if let Some(accounts) = interested_tx.try_ray_init2() {
+                        let accounts = accounts.into_iter().cloned().collect::<Vec<_>>();
+                        trade.migrate_to_raydium(&accounts, interested_tx.logs.clone())
+                    } else if let Some(accounts) = interested_tx
+                        .pump_amm_swaps()
+                        .into_iter()
+                        .find(|x| x[0] == pump_amm_canonical_pool(&trade.mint()))
+                    {
+                        // a routed tx can swap through other PumpSwap pools first
+                        let Some(log) = interested_tx.log_for(&accounts[0]) else {
+                            continue;
+                        };
+                        trade.migrate_to_pump_amm(&accounts, log)
+                    } else {
+                        continue;
+                    };
+                    match migrated {
+                        Ok(trade) => {
+                            info!("trade {} migrated to {:?}", trade.id, trade.amm);
+                            db.upsert(&trade);
+                            chan.bg
+                                .try_send(InternalCommand::TradeConfirmation(trade.clone()))
+                                .unwrap();
+                            presign(chan.clone(), &trade);
+                        }
+                        Err(e) => error!("migrate {trade_id} {e}"),
+                    }
+                }
+                InternalCommand::LimitExpired(trade) => {
+                    expire_limit(trade, &mut db);
+                }
                 InternalCommand::PumpSwapMaybe {
                     trade,
                     interested_tx: event,
                 } => {
-                    let accounts_maybe = event.try_pump_swap();
-                    if accounts_maybe.is_none() {
+                    let signature = event.signature;
+                    let Some(trade) = event.with_swap_pool(trade) else {
                         // info!("skipping {:?} {}",event.accounts, event.signature.to_string());
                         continue;
-                    }
-
-                    let valid_accounts = accounts_maybe.unwrap().into_iter().collect::<Vec<_>>();
-                    let InterestedTx {
-                        signature,
-                        logs,
-                        message,
-                        ..
-                    } = event;
-
-                    let mut trade = trade.with_pump_swap(valid_accounts.as_slice(), logs);
+                    };
                     if let Err(e) = trade {
                         error!("{e}");
                         continue;
@@ -392,18 +820,10 @@ pub async fn trade_chan(chan: Chan, mut rec: Receiver<InternalCommand>) {
                     info!("price {}", trade.price);
                     // info!("pump price = {}",trade.price);
 
-                    // // To introduce sending dynamic rust code
-                    // let mut buy = false;
-                    // if let ProgramLogInfo::PumpTradeLog(d) = trade.plog.log {
-                    //     if d.sol_amount >= LAMPORTS_PER_SOL*1 &&
-                    //         d.token_amount.to_f64().unwrap() >= (37951768488745.0 * 0.95)  &&
-                    //         d.token_amount.to_f64().unwrap() >= (37951768488745.0 * 1.05)
-                    //     {
-                    //         buy = true;
-                    //     }
-                    // }
-
-                    if trade.rpc_status == RpcState::Free && trade.state == TradeState::Buy {
+                    if trade.rpc_status == RpcState::Free
+                        && trade.state == TradeState::Buy
+                        && strategies.on_log(&trade, &event, &db) == StrategyIntent::Buy
+                    {
                         info!("time to buy bro {} {}", trade.price, signature.to_string());
                         swap(chan.clone(), trade, &mut db);
                     }
@@ -446,6 +866,35 @@ pub fn poll_trade_and_emit<F>(
     });
 }
 
+/// Polls the block engine until the bundle is confirmed or failed, emits every status change
+pub fn poll_bundle_and_emit(trade_id: i64, bundle_id: String, chan: Chan) {
+    tokio::spawn(async move {
+        let mut last = None;
+        for _ in 0..30 {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            let status = match jito_bundle_status(&bundle_id).await {
+                Ok(Some(status)) => status,
+                Ok(None) => continue,
+                Err(e) => {
+                    error!("bundle status {bundle_id} {e}");
+                    continue;
+                }
+            };
+            if last.as_ref() != Some(&status) {
+                last = Some(status.clone());
+                let _ = chan.trade.try_send(InternalCommand::BundleStatus {
+                    trade_id,
+                    bundle_id: bundle_id.clone(),
+                    status: status.clone(),
+                });
+            }
+            if status == "confirmed" || status == "finalized" || status.starts_with("failed") {
+                break;
+            }
+        }
+    });
+}
+
 #[derive(Debug, Clone)]
 pub struct InterestedTx {
     pub signature: Signature,
@@ -456,6 +905,10 @@ pub struct InterestedTx {
 }
 
 impl InterestedTx {
+    /// Pump launches and graduations in this tx, see `ProgramLog::pump_events`
+    pub fn pump_events(&self) -> Vec<ProgramLog> {
+        ProgramLog::pump_events(&self.meta.log_messages)
+    }
     pub fn try_ray_init2(&self) -> Option<Vec<&Pubkey>> {
         self.message.instructions.iter().find_map(|x| {
             if x.accounts.len() == 21 {
@@ -512,7 +965,106 @@ impl InterestedTx {
             }
         })
     }
-    pub fn try_pump_swap(&self) -> Option<Vec<Pubkey>> {
+    /// Moves `trade` to the pool this tx swapped its mint in. A routed tx can go through
+    /// several pools, each is tried with its own event until one is for the trade's mint.
+    pub fn with_swap_pool(&self, trade: Trade) -> Option<anyhow::Result<Trade>> {
+        type WithPool = fn(Trade, &[Pubkey], String) -> anyhow::Result<Trade>;
+        // accounts with the key its event names
+        let candidates = self
+            .pump_swaps()
+            .into_iter()
+            .map(|x| (x[2], x, Trade::with_pump_swap as WithPool))
+            .chain(self.ray_cp_swaps().into_iter().map(|x| (x[3], x, Trade::with_ray_cp_swap as WithPool)))
+            .chain(self.ray_clmm_swaps().into_iter().map(|x| (x[2], x, Trade::with_ray_clmm_swap as WithPool)))
+            .chain(self.pump_amm_swaps().into_iter().map(|x| (x[0], x, Trade::with_pump_amm_swap as WithPool)));
+        let mut res = None;
+        for (key, accounts, with_pool) in candidates {
+            let Some(log) = self.log_for(&key) else {
+                continue;
+            };
+            match with_pool(trade.clone(), accounts.as_slice(), log) {
+                Ok(trade) => return Some(Ok(trade)),
+                Err(e) => res = Some(Err(e)),
+            }
+        }
+        res
+    }
+    /// The swap event for a pool, or a curve's mint, see `ProgramLog::pool_or_mint`
+    pub fn log_for(&self, key: &Pubkey) -> Option<String> {
+        self.meta
+            .log_messages
+            .iter()
+            .filter(|x| SWAP_EVENT_MARKERS.iter().any(|m| x.contains(m)))
+            .find(|x| {
+                ProgramLog::from((*x).clone())
+                    .ok()
+                    .and_then(|l| l.pool_or_mint())
+                    .as_ref()
+                    == Some(key)
+            })
+            .cloned()
+    }
+    /// Outer and inner instructions of `program`, data and accounts
+    fn program_instructions(&self, program: &Pubkey) -> Vec<(&[u8], Vec<Pubkey>)> {
+        let outer = self
+            .message
+            .instructions
+            .iter()
+            .map(|x| (x.program_id_index, x.data.as_slice(), x.accounts.as_slice()));
+        let inner = self
+            .meta
+            .inner_instructions
+            .iter()
+            .flat_map(|x| x.instructions.iter())
+            .map(|x| (x.program_id_index, x.data.as_slice(), x.accounts.as_slice()));
+        outer
+            .chain(inner)
+            .filter(|(index, _, _)| self.accounts.get(*index as usize) == Some(program))
+            .filter_map(|(_, data, accounts)| {
+                let keys = accounts
+                    .iter()
+                    .filter_map(|x| self.accounts.get(*x as usize).copied())
+                    .collect::<Vec<_>>();
+                // accounts from lookup tables are not in `accounts`
+                (keys.len() == accounts.len()).then_some((data, keys))
+            })
+            .collect()
+    }
+    /// Accounts of each CP-Swap swap, either direction
+    pub fn ray_cp_swaps(&self) -> Vec<Vec<Pubkey>> {
+        self.program_instructions(&RAYDIUM_CP_PROGRAM)
+            .into_iter()
+            .filter(|(data, accounts)| {
+                accounts.len() >= 13
+                    && (data.starts_with(&RAYDIUM_CP_SWAP_BASE_INPUT_CODE)
+                        || data.starts_with(&RAYDIUM_CP_SWAP_BASE_OUTPUT_CODE))
+            })
+            .map(|(_, accounts)| accounts)
+            .collect()
+    }
+    /// Accounts of each CLMM `swap_v2`, the older `swap` doesn't name the mints
+    pub fn ray_clmm_swaps(&self) -> Vec<Vec<Pubkey>> {
+        self.program_instructions(&RAYDIUM_CLMM_PROGRAM)
+            .into_iter()
+            .filter(|(data, accounts)| accounts.len() >= 13 && data.starts_with(&RAYDIUM_CLMM_SWAP_V2_CODE))
+            .map(|(_, accounts)| accounts)
+            .collect()
+    }
+    /// Accounts of each PumpSwap `buy` or `sell`, they share the curve's instruction codes
+    pub fn pump_amm_swaps(&self) -> Vec<Vec<Pubkey>> {
+        self.program_instructions(&PUMP_AMM_PROGRAM)
+            .into_iter()
+            .filter(|(data, accounts)| {
+                accounts.len() >= 17 && (data.starts_with(&PUMP_BUY_CODE) || data.starts_with(&PUMP_SELL_CODE))
+            })
+            .map(|(_, accounts)| accounts)
+            .collect()
+    }
+    pub fn try_pump_amm_swap(&self) -> Option<Vec<Pubkey>> {
+        self.pump_amm_swaps().into_iter().next()
+    }
+    /// Accounts of each pump curve buy or sell
+    pub fn pump_swaps(&self) -> Vec<Vec<Pubkey>> {
         let mut maybe_keys = vec![];
 
         for inners in &self.meta.inner_instructions {
@@ -541,7 +1093,7 @@ impl InterestedTx {
             }
         }
 
-        let mut resp = None;
+        let mut resp = vec![];
 
         for accounts in maybe_keys {
             let mut correct = vec![];
@@ -582,7 +1134,7 @@ impl InterestedTx {
                 // if resp.is_some() {
                 //     info!("again again again {:?} {:?}", correct, resp.unwrap());
                 // }
-                resp = Some(correct.clone());
+                resp.push(correct.clone());
             }
         }
         resp
@@ -635,6 +1187,8 @@ pub fn decode_tx(update: Result<SubscribeUpdate, Status>, db: &Cache) -> Vec<Int
         // DO NOT SUPPORT RAY AND PUMP IN SAME TX
         let mut ray_log_maybe = None;
         let mut pump_log_maybe = None;
+        let mut ray_pool_log_maybe = None;
+        let mut pump_amm_log_maybe = None;
         for x in &meta.log_messages {
             if x.contains("ray_log") {
                 ray_log_maybe = Some(x);
@@ -642,6 +1196,14 @@ pub fn decode_tx(update: Result<SubscribeUpdate, Status>, db: &Cache) -> Vec<Int
             } else if x.contains("vdt/007mYe") {
                 pump_log_maybe = Some(x);
                 break;
+            } else if x.contains("QMbN6CYI") {
+                // cp-swap or clmm SwapEvent
+                ray_pool_log_maybe = Some(x);
+                break;
+            } else if x.contains("Z/RSHyz1") || x.contains("Pi83CqUD") {
+                // pump amm BuyEvent or SellEvent
+                pump_amm_log_maybe = Some(x);
+                break;
             }
         }
 
@@ -650,20 +1212,81 @@ pub fn decode_tx(update: Result<SubscribeUpdate, Status>, db: &Cache) -> Vec<Int
             log = l.clone();
         } else if let Some(l) = pump_log_maybe {
             log = l.clone();
+        } else if let Some(l) = ray_pool_log_maybe {
+            log = l.clone();
+        } else if let Some(l) = pump_amm_log_maybe {
+            log = l.clone();
         }
 
         if log.is_empty() {
             return vec![];
         }
 
+        let completed = interested_tx
+            .pump_events()
+            .into_iter()
+            .filter_map(|x| match x.log {
+                ProgramLogInfo::PumpCompleteLog(c) => Some(c.mint),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let ray_init = ray_log_maybe.is_some()
+            && meta.log_messages.iter().any(|z| z.contains("init_pc_amount"))
+            && interested_tx.try_ray_init2().is_some();
+        let pump_amm_swap = pump_amm_log_maybe.is_some() && interested_tx.try_pump_amm_swap().is_some();
+
         for x in watch_trades {
+            if matches!(x.amm, TradePool::PumpBondingCurve(_)) && !x.is_admin() {
+                let completing = completed.contains(&x.mint());
+                if completing {
+                    txs.push(InternalCommand::CurveComplete(x.id));
+                }
+                // the curve trade can't take a raydium log, it moves to the new pool instead
+                if let (true, true, Some(ray_log)) = (x.migrating || completing, ray_init, ray_log_maybe) {
+                    let mut interested_tx = interested_tx.clone();
+                    interested_tx.logs = ray_log.clone();
+                    txs.push(InternalCommand::PoolMigrated {
+                        trade_id: x.id,
+                        interested_tx,
+                    });
+                    continue;
+                }
+                // or to the pump amm pool, from its first swap seen after the curve completed
+                if let (true, true, Some(amm_log)) = (x.migrating, pump_amm_swap, pump_amm_log_maybe) {
+                    let mut interested_tx = interested_tx.clone();
+                    interested_tx.logs = amm_log.clone();
+                    txs.push(InternalCommand::PoolMigrated {
+                        trade_id: x.id,
+                        interested_tx,
+                    });
+                    continue;
+                }
+            }
             let mut trade = x.clone();
+            // the copy is upserted after `CurveComplete`, it must not clear the flag again
+            if matches!(x.amm, TradePool::PumpBondingCurve(_)) && !x.is_admin() && completed.contains(&x.mint()) {
+                trade.migrating = true;
+            }
 
-            let trade = trade.update_price_from_log(log.clone(), true);
+            // a routed tx has several swap events, the trade's pool has its own
+            let key = match x.amm {
+                TradePool::PumpBondingCurve(z) => Some(z.mint),
+                _ if x.has_pool() => Some(x.amm()),
+                _ => None,
+            };
+            let log = key.and_then(|k| interested_tx.log_for(&k)).unwrap_or_else(|| log.clone());
+            let trade = trade.update_price_from_log(log, true);
             match trade {
                 Ok(trade) => match trade.state {
                     TradeState::Buy => {
-                        if let Some(pump_log) = pump_log_maybe {
+                        if trade.limit_expired() {
+                            txs.push(InternalCommand::LimitExpired(trade));
+                            continue;
+                        }
+                        if !trade.limit_reached() {
+                            continue;
+                        }
+                        if let Some(pump_log) = pump_log_maybe.or(ray_pool_log_maybe).or(pump_amm_log_maybe) {
                             interested_tx.logs = pump_log.clone();
                             txs.push(InternalCommand::PumpSwapMaybe {
                                 trade,
@@ -686,13 +1309,22 @@ pub fn decode_tx(update: Result<SubscribeUpdate, Status>, db: &Cache) -> Vec<Int
                     TradeState::PendingBuy
                     | TradeState::BuySuccess
                     | TradeState::PendingSell
-                    | TradeState::SellSuccess => {
+                    | TradeState::SellSuccess
+                    | TradeState::PartiallyClosed => {
+                        let mut trade = trade;
+                        let mut interested_tx = interested_tx.clone();
+                        // paper swaps land right after the observed tx, at its reserves
+                        if let Some(signature) = trade.paper_signature() {
+                            trade.plog.amount_out = trade.paper_amount_out();
+                            interested_tx.signature = signature;
+                        }
                         txs.push(InternalCommand::TradeUpdate {
                             trade,
-                            interested_tx: interested_tx.clone(),
+                            interested_tx,
                         });
                     }
-                    _ => unreachable!(),
+                    // failed buys leave the cache, failed sells wait for a retry
+                    TradeState::BuyFailed | TradeState::SellFailed => {}
                 },
                 Err(e) => {
                     error!("{e}");
@@ -702,3 +1334,103 @@ pub fn decode_tx(update: Result<SubscribeUpdate, Status>, db: &Cache) -> Vec<Int
     }
     txs
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
+    use base64::Engine;
+    use serde::Serialize;
+
+    fn program_data<T: Serialize>(code: [u8; 8], event: &T) -> String {
+        let bytes = [code.as_slice(), &bincode::serialize(event).unwrap()].concat();
+        format!("Program data: {}", BASE64_STANDARD.encode(bytes))
+    }
+
+    fn curve_trade(mint: Pubkey) -> Trade {
+        let mut trade = Trade::dangerous_user(Keypair::new().to_bytes().to_vec());
+        trade.id = 1;
+        trade.state = TradeState::BuySuccess;
+        trade.buy_price = Some(dec!(0.00000003));
+        trade.amm = TradePool::PumpBondingCurve(PumpBondingCurve {
+            amm: Pubkey::new_unique(),
+            vault: Pubkey::new_unique(),
+            mint,
+            decimals: Some(6),
+            token_program_id: spl_token::id(),
+        });
+        trade
+    }
+
+    /// The buy that sells out the curve, its trade and complete events
+    fn completing_tx(mint: Pubkey) -> SubscribeUpdate {
+        let user = Pubkey::new_unique();
+        let swap = PumpTradeLog {
+            mint,
+            sol_amount: 1_000_000_000,
+            token_amount: 10_000_000_000_000,
+            is_buy: true,
+            user,
+            timestamp: 1,
+            virtual_sol_reserves: 115_005_359_056,
+            virtual_token_reserves: 279_900_000_000_000,
+        };
+        let complete = PumpCompleteLog {
+            user,
+            mint,
+            bonding_curve: Pubkey::new_unique(),
+            timestamp: 1,
+        };
+        SubscribeUpdate {
+            update_oneof: Some(UpdateOneof::Transaction(SubscribeUpdateTransaction {
+                transaction: Some(SubscribeUpdateTransactionInfo {
+                    signature: vec![1; 64],
+                    transaction: Some(Transaction {
+                        message: Some(Message {
+                            account_keys: vec![user.to_bytes().to_vec(), mint.to_bytes().to_vec()],
+                            ..Default::default()
+                        }),
+                        ..Default::default()
+                    }),
+                    meta: Some(TransactionStatusMeta {
+                        log_messages: vec![
+                            format!("Program {PUMP_PROGRAM} invoke [1]"),
+                            program_data(PUMP_SWAP_CODE, &swap),
+                            program_data(PUMP_COMPLETE_CODE, &complete),
+                            format!("Program {PUMP_PROGRAM} success"),
+                        ],
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn completing_tx_keeps_trade_migrating() {
+        let mint = Pubkey::new_unique();
+        let mut db = Cache {
+            trades: HashMap::new(),
+            mints: HashMap::new(),
+            oneshots: HashMap::new(),
+            jito_tip: None,
+            sends_left: HashMap::new(),
+        };
+        db.upsert(&curve_trade(mint));
+
+        let commands = decode_tx(Ok(completing_tx(mint)), &db);
+
+        assert!(commands.iter().any(|x| matches!(x, InternalCommand::CurveComplete(1))));
+        let update = commands
+            .iter()
+            .find_map(|x| match x {
+                InternalCommand::TradeUpdate { trade, .. } if trade.id == 1 => Some(trade),
+                _ => None,
+            })
+            .expect("trade update");
+        assert!(update.migrating);
+    }
+}
@@ -1,3 +1,4 @@
+use rust_decimal::Decimal;
 use solana_sdk::signature::Keypair;
 use crate::trade_chan::*;
 use crate::trade::*;
@@ -12,9 +13,38 @@ pub enum InternalCommand {
     LoginRequest(Keypair,tokio::sync::oneshot::Sender<InternalCommand>),
     SwapRequest(Trade),
     SwapLimitRequest(Trade, tokio::sync::oneshot::Sender<InternalCommand>),
+    /// every trade in the engine's cache, answered with `CachedTradesResponse`
+    CachedTrades(tokio::sync::oneshot::Sender<InternalCommand>),
+    CachedTradesResponse(Vec<Trade>),
+    /// trade id, fraction of held tokens to sell, pending buy to bundle the sell behind
+    ScaleOutRequest(i64, Decimal, Option<i64>),
+    /// trade id, SOL to add
+    ScaleInRequest(i64, Decimal),
     DslResponse(String),
     Dsl(String,String,tokio::sync::oneshot::Sender<InternalCommand>),
     RpcTradeResponse(Result<Trade,Trade>),
+    /// response to resending a pending swap, only its rpc log is kept
+    RebroadcastResponse(Result<Trade,Trade>),
+    LimitExpired(Trade),
+    /// an expired buy after `Trade::reconcile`, filled, failed or still pending when the lookup failed
+    ExpiredBuyChecked(Trade),
+    BundleStatus {
+        trade_id: i64,
+        bundle_id: String,
+        status: String,
+    },
+    PresignedExits {
+        trade_id: i64,
+        exits: Vec<PresignedExit>,
+    },
+    /// trade id, its pump curve completed
+    CurveComplete(i64),
+    /// a Raydium or PumpSwap pool was created for the trade's curve coin
+    PoolMigrated {
+        trade_id: i64,
+        interested_tx: InterestedTx
+    },
+    /// a pump, CP-Swap, CLMM or PumpSwap swap that may price or enter a trade waiting to buy
     PumpSwapMaybe {
         trade: Trade,
         interested_tx: InterestedTx
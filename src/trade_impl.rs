@@ -3,12 +3,13 @@ use std::error::Error;
 use std::fmt;
 use std::str::FromStr;
 use std::time::Duration;
+use chrono::{DateTime, Utc};
 use anyhow::anyhow;
 use axum::http::StatusCode;
 use base64::Engine;
 use futures::stream::FuturesUnordered;
 use futures::TryStreamExt;
-use log::info;
+use log::{error, info, warn};
 use mongodb::{bson, Collection};
 use mongodb::bson::{doc, Binary};
 use redis::aio::ConnectionManager;
@@ -21,18 +22,30 @@ use serde_json::json;
 use solana_sdk::compute_budget::ComputeBudgetInstruction;
 use solana_sdk::signature::{Keypair, Signature};
 use solana_sdk::signer::Signer;
-use solana_sdk::transaction::Transaction;
+use solana_sdk::hash::Hash;
+use solana_sdk::message::{v0, VersionedMessage};
+use solana_sdk::transaction::{Transaction, VersionedTransaction};
 use spl_associated_token_account_client::address::get_associated_token_address_with_program_id;
 use tokio::sync::oneshot;
 use tokio::task::JoinHandle;
+use common::common_utils::{get_transfer_fee, get_transfer_inverse_fee, unpack_mint};
+use spl_token_2022::extension::{BaseStateWithExtensions, ExtensionType};
+use spl_token_2022::extension::default_account_state::DefaultAccountState;
+use spl_token_2022::state::AccountState;
 use raydium_amm::solana_program::instruction::{AccountMeta, Instruction};
 use raydium_amm::solana_program::native_token::LAMPORTS_PER_SOL;
 use raydium_amm::solana_program::pubkey::Pubkey;
+use crate::blockhash::*;
+use crate::lookup_table::lookup_table;
+use crate::nonce::{cached_nonce, durable_nonce};
 use crate::swap_config::*;
 use crate::chan::*;
 use crate::constant::*;
 use crate::plog::*;
 use crate::pump::*;
+use crate::raydium::*;
+use crate::pump_amm::*;
+use crate::quote::*;
 use crate::solana::*;
 use crate::trade::*;
 use crate::trade_cmd::*;
@@ -50,9 +63,12 @@ impl fmt::Display for Trade {
             TradePool::RayAmm4(_) => {
                 unimplemented!()
             }
-            TradePool::PumpBondingCurve(_) => {
+            TradePool::PumpBondingCurve(_) | TradePool::PumpAmm(_) => {
                 format!("https://pump.fun/coin/{}",self.mint().to_string())
             }
+            TradePool::RayCpmm(_) | TradePool::RayClmm(_) => {
+                format!("https://raydium.io/swap/?inputMint=sol&outputMint={}",self.mint().to_string())
+            }
             _ => unreachable!()
         };
         let pct = self.pct;
@@ -110,6 +126,18 @@ impl Trade {
         r
     }
 
+    /// Trades signed by one of `wallets` that are not closed yet, a trade's `user_id` is not always set
+    pub async fn db_get_active_by_wallets(
+        c: &mongodb::Collection<Self>,
+        wallets: &[Pubkey],
+    ) -> Vec<Trade> {
+        Self::db_get_active_trades(c)
+            .await
+            .into_iter()
+            .filter(|x| wallets.contains(&x.root_kp().pubkey()))
+            .collect()
+    }
+
     pub async fn db_get_users_mongo(
         c: &mongodb::Collection<Self>,
     ) -> Vec<Self> {
@@ -131,8 +159,14 @@ impl Trade {
         chan: Chan,
     ) -> anyhow::Result<Trade> {
         let (s, r) = oneshot::channel::<InternalCommand>();
+        // wait until the order expires, engine replies on fill or expiry
+        let wait = self
+            .limit
+            .and_then(|x| (x.expiry - Utc::now()).to_std().ok())
+            .unwrap_or_default()
+            + Duration::from_secs(5);
         chan.trade.try_send(InternalCommand::SwapLimitRequest(self.clone(), s))?;
-        match tokio::time::timeout(Duration::from_secs(5), r).await {
+        match tokio::time::timeout(wait, r).await {
             Ok(Ok(InternalCommand::TradeResponse(trade))) => {
                 Ok(trade)
             }
@@ -190,9 +224,30 @@ impl Trade {
             k: Default::default(),
             tvl: Default::default(),
             cfg: Default::default(),
+            strategy: None,
+            limit: None,
+            paper: false,
             error: None,
             plog: Default::default(),
             buy_out: None,
+            high_price: None,
+            tp_level: 0,
+            sell_amount: None,
+            sell_tp_level: None,
+            fills: vec![],
+            jito_tip: None,
+            bundle_head: vec![],
+            last_valid_block_height: None,
+            last_sent: None,
+            retries: 0,
+            needs_attention: false,
+            state_history: vec![],
+            mint_info: None,
+            presigned_exits: vec![],
+            sell_trigger_price: None,
+            nonce_in_flight: None,
+            migrating: false,
+            scale_in_sol: None,
 
             instructions: vec![],
             transactions: vec![],
@@ -208,6 +263,23 @@ impl Trade {
         self.rpc_logs.extend_from_slice(log);
         self.rpc_logs.dedup();
     }
+    /// Takes what `send_many` built onto the cached trade, the rest may have moved while it was in flight.
+    /// A response to an older send only adds its rpc logs.
+    pub fn merge_sent(&mut self, sent: &Trade) {
+        self.extend_rpc_logs(&sent.rpc_logs);
+        if sent.last_sent != self.last_sent {
+            return;
+        }
+        self.last_valid_block_height = sent.last_valid_block_height;
+        self.instructions = sent.instructions.clone();
+        self.transactions = sent.transactions.clone();
+        if sent.mint_info.is_some() {
+            self.mint_info = sent.mint_info.clone();
+        }
+        if !self.pool_loaded() && sent.pool_loaded() && sent.amm() == self.amm() {
+            self.amm = sent.amm;
+        }
+    }
     pub fn push_rpc_logs(&mut self, log:TradeRpcLog){
         self.rpc_logs.push(log);
         self.rpc_logs.dedup();
@@ -222,7 +294,13 @@ impl Trade {
         new_trade: NewTrade
     ) ->anyhow::Result<Self>{
         let NewTrade{amm,user_wallet, log} = new_trade;
+        let mint = self.mint();
+        let admin = self.is_admin();
         self.amm = amm;
+        // a routed tx swaps other mints too, the root trade follows them all
+        if !admin && self.mint() != mint {
+            return Err(anyhow!("pool {} is for {}, trade {} wants {mint}", self.amm(), self.mint(), self.id));
+        }
         self.user_wallet = user_wallet.to_string();
         self.update_price_from_log(log, true)
     }
@@ -254,6 +332,8 @@ impl Trade {
         json["mint"] = json!(self.mint().to_string());
         json["jito_tip"] = json!(jito_tip);
         json["error"] = json!(self.error);
+        json["realized_pnl"] = json!(self.realized_pnl());
+        json["unrealized_pnl"] = json!(self.unrealized_pnl());
         json.to_string()
     }
     pub fn signature_unchecked(&self) -> Signature {
@@ -264,50 +344,787 @@ impl Trade {
         self.rpc_logs.iter().find_map(|x|Some(x.signature()))
     }
     pub fn jito_fee_sol_ui(&self) ->Decimal {
-        (self.cfg.jito_tip_percent / dec!(2)) * self.cfg.fee()
+        let fixed = (self.cfg.jito_tip_percent / dec!(2)) * self.fee_sol_ui();
+        match self.cfg.jito_tip_policy {
+            JitoTipPolicy::Fixed => fixed,
+            JitoTipPolicy::Max => self.cfg.max_jito,
+            policy => self
+                .jito_tip
+                .and_then(|x| policy.tip_sol_ui(&x))
+                .map(|x| x.min(self.cfg.max_jito))
+                // no tip stream yet
+                .unwrap_or(fixed),
+        }
     }
     pub fn jito_fee_sol(&self) ->Decimal {
         let jito_fee_sol = self.jito_fee_sol_ui() * lamports_per_sol_dec();
         jito_fee_sol
     }
+    /// Fee budget in SOL, raised by `cfg.fee_bump` for every retry of the pending swap
+    pub fn fee_sol_ui(&self) -> Decimal {
+        (dec!(1) + self.cfg.fee_bump).powi(self.retries as i64) * self.cfg.fee()
+    }
+    /// Jito tip, priority and base fee paid by one swap
+    pub fn swap_fee_sol_ui(&self) -> Decimal {
+        self.jito_fee_sol_ui()
+            + (self.cfg.jito_priority_percent / dec!(2)) * self.fee_sol_ui()
+            + self.cfg.base_fee
+    }
     pub fn jito_pfee_micro_sol(&self) -> Decimal {
-        let pfee_sol_normal = (self.cfg.jito_priority_percent / dec!(2)) * self.cfg.fee();
+        let pfee_sol_normal = (self.cfg.jito_priority_percent / dec!(2)) * self.fee_sol_ui();
         let pfee_micro_sol = pfee_sol_normal * micro_lamports_per_sol_dec();
         pfee_micro_sol
     }
-    pub async fn build_transactions(mut self) ->Self{
-        let hash = if let TradeRpc::General {rpc:read_rpc,..} = rpc1() {
-            read_rpc.get_latest_blockhash().await.unwrap()
+    /// Signs the instructions with a recent blockhash, Err with `error` set when there is no blockhash
+    /// or a transaction can't be compiled
+    pub async fn build_transactions(mut self) ->Result<Self,Self>{
+        let recent = match recent_blockhash() {
+            Some(x) => x,
+            // tracker not started yet
+            None => match solana_rpc_client()
+                .get_latest_blockhash_with_commitment(solana_sdk::commitment_config::CommitmentConfig::confirmed())
+                .await
+            {
+                Ok((hash, last_valid_block_height)) => RecentBlockhash { hash, last_valid_block_height },
+                Err(e) => {
+                    self.error = Some(format!("latest blockhash {e}"));
+                    return Err(self);
+                }
+            },
+        };
+        let hash = recent.hash;
+        self.last_valid_block_height = Some(recent.last_valid_block_height);
+        match self.sign_transactions(hash) {
+            Ok(transactions) => {
+                self.transactions = transactions;
+                Ok(self)
+            }
+            Err(e) => {
+                self.error = Some(e.to_string());
+                Err(self)
+            }
+        }
+    }
+
+    /// Message for `ix`, v0 with the wallet's lookup table when `cfg.versioned`, legacy otherwise
+    fn compile_message(&self, ix: &[Instruction], hash: Hash) -> anyhow::Result<VersionedMessage> {
+        let kp = self.root_kp();
+        if !self.cfg.versioned {
+            return Ok(VersionedMessage::Legacy(solana_sdk::message::Message::new_with_blockhash(ix, Some(&kp.pubkey()), &hash)));
+        }
+        // none until the wallet's table is ready, v0 still works without it
+        let tables = lookup_table(&kp).into_iter().collect::<Vec<_>>();
+        v0::Message::try_compile(&kp.pubkey(), ix, &tables, hash)
+            .map(VersionedMessage::V0)
+            .map_err(|e| anyhow::format_err!("compile v0 message {e}"))
+    }
+
+    /// Signs every instruction set with `hash`, a blockhash or a durable nonce value
+    fn sign_transactions(&self, hash: Hash) -> anyhow::Result<Vec<TradeTransaction>> {
+        let kp = self.root_kp();
+        self.instructions.iter().map(|x|{
+            let message = self.compile_message(x.instructions().as_slice(), hash)?;
+            let tx = VersionedTransaction::try_new(message, &[&kp])
+                .map_err(|e| anyhow::format_err!("sign transaction {e}"))?;
+            Ok(match x {
+                TradeInstruction::Jito(_) => TradeTransaction::Jito(tx),
+                TradeInstruction::General(_) => TradeTransaction::General(tx),
+                TradeInstruction::JitoTip(_) => TradeTransaction::JitoTip(tx),
+            })
+        }).collect::<anyhow::Result<Vec<_>>>()
+    }
+
+    /// Signs the full exit and the next take profit sell against the wallet's durable nonce,
+    /// so `trade_chan` can send them without a blockhash fetch. Only one of them can land.
+    /// Each min out is priced at the exit's trigger, a stop fires after the price already fell.
+    pub async fn presign_exits(&self) -> anyhow::Result<Vec<PresignedExit>> {
+        let kp = self.root_kp();
+        let (nonce, nonce_hash) = durable_nonce(&kp).await?;
+        let mut fractions = vec![(dec!(1), self.stop_price())];
+        if let Some(level) = self.cfg.tp_level(self.tp_level) {
+            fractions.push((level.fraction, self.buy_price.map(|x| x * (dec!(1) + level.pct))));
+        }
+        let mut exits: Vec<PresignedExit> = vec![];
+        for (fraction, trigger) in fractions {
+            let mut sell = match self.clone().with_sell_fraction(fraction) {
+                Ok(x) => x,
+                Err(e) => {
+                    warn!("{e}");
+                    continue;
+                }
+            };
+            if exits.iter().any(|x| x.sell_amount == sell.sell_amount) {
+                continue;
+            }
+            // a separate tip transaction can't use the same nonce
+            sell.cfg.jito_bundle = false;
+            sell.nonce_in_flight = None;
+            sell.sell_trigger_price = trigger;
+            let mut sell = sell.rebuild_instructions();
+            // advancing the nonce has to be the first instruction
+            let advance = solana_sdk::system_instruction::advance_nonce_account(&nonce, &kp.pubkey());
+            for x in sell.instructions.iter_mut() {
+                x.instructions_mut().insert(0, advance.clone());
+            }
+            exits.push(PresignedExit {
+                sell_amount: sell.sell_amount,
+                amount: self.amount,
+                high_price: self.high_price,
+                nonce,
+                nonce_hash,
+                transactions: sell.sign_transactions(nonce_hash)?,
+                instructions: sell.instructions,
+            });
+        }
+        Ok(exits)
+    }
+
+    /// Presigned sell for the pending `sell_amount`, none when the position or the nonce moved since signing
+    pub fn presigned_exit(&self) -> Option<PresignedExit> {
+        let nonce_hash = cached_nonce(&self.root_kp().pubkey())?;
+        self.presigned_exits
+            .iter()
+            .find(|x| x.sell_amount == self.sell_amount && x.amount == self.amount && x.nonce_hash == nonce_hash)
+            .cloned()
+    }
+
+    /// Signature the simulated fill lands with, none when no paper swap is pending
+    pub fn paper_signature(&self) -> Option<Signature> {
+        if !self.paper || !matches!(self.state, TradeState::PendingBuy|TradeState::PendingSell) {
+            return None;
+        }
+        self.rpc_logs.iter().rev().find_map(|x| x.pending_signature())
+    }
+
+    /// Output of the pending swap against the reserves left by the last log,
+    /// lamports for sells and raw tokens for buys
+    pub fn paper_amount_out(&self) -> u64 {
+        let amount_in = if self.is_buy() {
+            (self.buy_sol() * lamports_per_sol_dec()).to_u64().unwrap()
         } else {
-            unreachable!()
+            self.sell_amount() - self.transfer_fee(self.sell_amount())
         };
-        let txs = self.instructions.iter().map(|x|{
-            match x {
-                TradeInstruction::Jito(_) => {
-                    TradeTransaction::Jito(
-                        Transaction::new_signed_with_payer(
-                            &x.instructions().as_slice(),
-                            Some(&self.root_kp().pubkey()),
-                            &[self.root_kp()],
-                            hash,
-                        )
-                    )
+        self.quote(amount_in, self.is_buy()).map_or(0, |x| x.amount_out)
+    }
+
+    /// Swap of exactly `amount_in` against the reserves left by the last log,
+    /// none while the trade has no pool or the log no reserves
+    pub fn quote(&self, amount_in: u64, buy: bool) -> Option<Quote> {
+        Quote::base_in(Reserves::new(&self.amm, &self.plog)?, amount_in, buy, self.decimals())
+    }
+
+    /// Swap that gets exactly `amount_out`, see [Trade::quote]
+    pub fn quote_out(&self, amount_out: u64, buy: bool) -> Option<Quote> {
+        Quote::base_out(Reserves::new(&self.amm, &self.plog)?, amount_out, buy, self.decimals())
+    }
+
+    /// Price a sale of every held token fills at, the spot price when it can't be quoted
+    pub fn exit_price(&self) -> Decimal {
+        let amount = self.amount.to_u64().unwrap_or_default();
+        let amount = amount - self.transfer_fee(amount);
+        self.quote(amount, false).map_or(self.price, |x| x.price)
+    }
+
+    pub fn is_pending(&self) -> bool {
+        matches!(self.state, TradeState::PendingBuy|TradeState::PendingSell)
+    }
+
+    pub fn is_failed(&self) -> bool {
+        matches!(self.state, TradeState::BuyFailed|TradeState::SellFailed)
+    }
+
+    /// SOL the next or pending buy spends
+    pub fn buy_sol(&self) -> Decimal {
+        self.scale_in_sol.unwrap_or(self.cfg.max_sol)
+    }
+
+    /// Moves a swap that can't land out of its pending state.
+    /// A failed scale in keeps the position it was adding to, a failed buy holds nothing.
+    /// A failed sell still holds tokens and is retried until `cfg.max_retries`.
+    pub fn fail_swap(&mut self) -> anyhow::Result<()> {
+        let to = if !self.is_buy() {
+            TradeState::SellFailed
+        } else if self.amount > Decimal::ZERO {
+            self.held_state()
+        } else {
+            TradeState::BuyFailed
+        };
+        self.transition(to, "swap failed")?;
+        self.update_rpc_status(RpcState::Free);
+        // the buy it was bundled behind landed alone or not at all, a retry goes out on its own
+        self.bundle_head.clear();
+        if to != TradeState::SellFailed {
+            self.scale_in_sol = None;
+        }
+        if self.is_open() {
+            self.retries = 0;
+        }
+        self.needs_attention = self.state == TradeState::SellFailed && self.retries >= self.cfg.max_retries;
+        Ok(())
+    }
+
+    /// Moves the trade to `to` and records it in `state_history`, illegal moves leave the trade as is
+    pub fn transition(&mut self, to: TradeState, reason: &str) -> anyhow::Result<()> {
+        if !self.state.can_transition(to) {
+            return Err(anyhow!("trade {} illegal transition {:?} -> {:?} ({reason})", self.id, self.state, to));
+        }
+        self.state_history.push(TradeStateEvent {
+            time: Utc::now(),
+            from: self.state,
+            to,
+            reason: reason.to_string(),
+        });
+        self.state = to;
+        Ok(())
+    }
+
+    /// Replays `state_history` from `TradeState::Buy`, fails on a gap or an illegal move
+    pub fn replay_state_history(&self) -> anyhow::Result<TradeState> {
+        let mut state = TradeState::Buy;
+        for event in &self.state_history {
+            if event.from != state || !state.can_transition(event.to) {
+                return Err(anyhow!("trade {} bad event {:?} -> {:?} at {}", self.id, event.from, event.to, event.time));
+            }
+            state = event.to;
+        }
+        Ok(state)
+    }
+
+    /// Corrects a trade loaded from the db against the chain, a swap may have landed while the engine was down.
+    /// Pending signatures are looked up and the wallet's token balance is compared to `amount`.
+    /// A pending swap that never landed fails, anything that doesn't add up sets `needs_attention`.
+    pub async fn reconcile(mut self) -> Trade {
+        let on_chain = matches!(
+            self.amm,
+            TradePool::RayAmm4(_)
+                | TradePool::PumpBondingCurve(_)
+                | TradePool::RayCpmm(_)
+                | TradePool::RayClmm(_)
+                | TradePool::PumpAmm(_)
+        );
+        if self.paper || !on_chain || matches!(self.state, TradeState::Buy | TradeState::BuyFailed | TradeState::SellSuccess) {
+            return self;
+        }
+        let rpc = solana_rpc_client();
+        let signatures = self.rpc_logs.iter().filter_map(|x| x.pending_signature()).collect::<Vec<_>>();
+        let landed = match rpc.get_signature_statuses_with_history(&signatures).await {
+            Ok(res) => signatures
+                .iter()
+                .zip(res.value)
+                .filter(|(_, x)| x.as_ref().map_or(false, |x| x.err.is_none()))
+                .map(|(signature, _)| *signature)
+                .last(),
+            Err(e) => return self.unreconciled(format!("signature statuses {e}")),
+        };
+        let ata = get_associated_token_address_with_program_id(&self.root_kp().pubkey(), &self.mint(), &self.token_program());
+        let held = match rpc.get_token_account_balance(&ata).await {
+            Ok(x) => Decimal::from_str(&x.amount).unwrap_or_default(),
+            // the account is closed after a full sell
+            Err(e) if e.to_string().contains("could not find account") => Decimal::ZERO,
+            Err(e) => return self.unreconciled(format!("token balance {e}")),
+        };
+
+        let res = match (self.state, landed) {
+            (TradeState::PendingBuy, Some(signature)) if held > self.amount => {
+                let received = (held - self.amount).to_u64().unwrap_or_default();
+                let amount_out = received + self.transfer_inverse_fee(received);
+                self.apply_buy_fill(signature, amount_out)
+            }
+            (TradeState::PendingBuy, None) if held == self.amount => {
+                self.error = Some("buy didn't land before restart".to_string());
+                self.fail_swap()
+            }
+            (TradeState::PendingSell, Some(signature)) if held < self.amount => {
+                self.sell_amount = if held > Decimal::ZERO {
+                    (self.amount - held).to_u64()
+                } else {
+                    None
+                };
+                self.apply_sell_fill(signature)
+            }
+            // the tick retries it like any failed sell
+            (TradeState::PendingSell, None) if held == self.amount => {
+                self.error = Some("sell didn't land before restart".to_string());
+                self.fail_swap()
+            }
+            (TradeState::BuySuccess | TradeState::PartiallyClosed | TradeState::SellFailed, _) if held == self.amount => Ok(()),
+            (state, landed) => Err(anyhow!("{state:?} landed {landed:?}, expected {} tokens, {held} held", self.amount)),
+        };
+        match res {
+            Ok(()) => {
+                info!("reconciled trade {} {:?}", self.id, self.state);
+                self
+            }
+            Err(e) => self.unreconciled(e.to_string()),
+        }
+    }
+
+    fn unreconciled(mut self, error: String) -> Trade {
+        error!("trade {} can't be reconciled, {error}", self.id);
+        self.error = Some(error);
+        self.needs_attention = true;
+        self
+    }
+
+    /// False while a CP-Swap, CLMM or PumpSwap pool's decimals are unknown, see [Trade::load_pool]
+    pub fn pool_loaded(&self) -> bool {
+        match self.amm {
+            TradePool::RayCpmm(z) => z.decimals.is_some(),
+            TradePool::RayClmm(z) => z.decimals.is_some(),
+            TradePool::PumpAmm(z) => z.decimals.is_some(),
+            _ => true,
+        }
+    }
+
+    /// Reads decimals, fee and tick spacing of a CP-Swap or CLMM pool, the swap logs don't carry them.
+    /// A PumpSwap pool's decimals come from its mint and the coin creator from the pool.
+    /// The price from the last log was worked out with 6 decimals, so it's worked out again.
+    /// A CLMM pool is read on every send, its current tick and tick arrays pick the swap's accounts.
+    pub async fn load_pool(mut self) -> Result<Trade,Trade> {
+        let loaded = self.pool_loaded();
+        if loaded && !matches!(self.amm, TradePool::RayClmm(_)) {
+            return Ok(self);
+        }
+        // the config, or the base mint of a PumpSwap pool
+        let (pool, config) = match self.amm {
+            TradePool::RayCpmm(z) => (z.pool, z.amm_config),
+            TradePool::RayClmm(z) => (z.pool, z.amm_config),
+            TradePool::PumpAmm(z) => (z.pool, z.base_mint),
+            _ => return Ok(self),
+        };
+        let sol_is_0 = match self.amm {
+            TradePool::RayCpmm(z) => z.token_0_mint == SOLANA_MINT,
+            TradePool::RayClmm(z) => z.token_0_mint == SOLANA_MINT,
+            _ => false,
+        };
+        let res = async {
+            let accounts = solana_rpc_client().get_multiple_accounts(&[pool, config]).await?;
+            let [Some(pool), Some(config)] = [&accounts[0], &accounts[1]] else {
+                return Err(anyhow!("pool {pool} or config {config} missing"));
+            };
+            let bad = || anyhow!("can't read pool {:?}", self.amm);
+            let mut amm = self.amm;
+            match amm {
+                TradePool::RayCpmm(ref mut z) => {
+                    let (d0, d1) = cp_pool_decimals(&pool.data).ok_or_else(bad)?;
+                    z.decimals = Some(if sol_is_0 { d1 } else { d0 } as i16);
+                    z.trade_fee_rate = Some(cp_config_fee_rate(&config.data).ok_or_else(bad)?);
                 }
-                TradeInstruction::General(_) => {
-                    TradeTransaction::General(
-                        Transaction::new_signed_with_payer(
-                            &x.instructions().as_slice(),
-                            Some(&self.root_kp().pubkey()),
-                            &[self.root_kp()],
-                            hash,
-                        )
-                    )
+                TradePool::RayClmm(ref mut z) => {
+                    let (d0, d1, tick_spacing, tick) = clmm_pool_state(&pool.data).ok_or_else(bad)?;
+                    z.decimals = Some(if sol_is_0 { d1 } else { d0 } as i16);
+                    z.trade_fee_rate = Some(clmm_config_fee_rate(&config.data).ok_or_else(bad)?);
+                    z.tick_spacing = Some(tick_spacing);
+                    z.tick_current = Some(tick);
+                    z.tick_array_bitmap = Some(clmm_pool_bitmap(&pool.data).ok_or_else(bad)?);
+                }
+                TradePool::PumpAmm(ref mut z) => {
+                    z.decimals = Some(mint_decimals(&config.data).ok_or_else(bad)? as i16);
+                    let coin_creator = pump_amm_pool_coin_creator(&pool.data).ok_or_else(bad)?;
+                    z.coin_creator_vault_authority = z
+                        .coin_creator_vault_authority
+                        .or(Some(pump_amm_creator_vault_authority(&coin_creator)));
+                }
+                _ => {}
+            }
+            anyhow::Ok(amm)
+        }.await;
+        match res {
+            Ok(amm) => self.amm = amm,
+            Err(e) => {
+                self.error = Some(format!("pool {e}"));
+                return Err(self);
+            }
+        }
+        if !loaded && !matches!(self.plog.log, ProgramLogInfo::Empty) {
+            let plog = self.plog.clone();
+            if let Ok(mut trade) = self.clone().update_price_from_plog(plog, true) {
+                // the log's tick is older than the one just read
+                if let (TradePool::RayClmm(z), TradePool::RayClmm(read)) = (&mut trade.amm, self.amm) {
+                    z.tick_current = read.tick_current;
                 }
+                self = trade;
+            }
+        }
+        Ok(self)
+    }
+
+    /// Finds the program that owns the mint, spl-token pools skip the rpc call.
+    /// Token-2022 mints with an extension that can take, freeze or block the tokens are rejected.
+    pub async fn load_mint(mut self) -> Result<Trade,Trade> {
+        if self.mint_info.is_some() || self.paper {
+            return Ok(self);
+        }
+        let pool_program = match self.amm {
+            TradePool::RayAmm4(z) => z.token_program_id,
+            TradePool::PumpBondingCurve(z) => z.token_program_id,
+            TradePool::RayCpmm(z) if z.token_0_mint == SOLANA_MINT => z.token_1_program,
+            TradePool::RayCpmm(z) => z.token_0_program,
+            // the swap takes both token programs, the mint tells which one it is
+            TradePool::RayClmm(_) => spl_token_2022::id(),
+            TradePool::PumpAmm(z) => z.base_token_program,
+            _ => return Ok(self),
+        };
+        if pool_program == spl_token::id() {
+            return Ok(self);
+        }
+        let rpc = solana_rpc_client();
+        let res = async {
+            let account = rpc.get_account(&self.mint()).await?;
+            let epoch = rpc.get_epoch_info().await?.epoch;
+            anyhow::Ok(MintInfo {
+                program: account.owner,
+                data: if account.owner == spl_token_2022::id() { account.data } else { vec![] },
+                epoch,
+            })
+        }.await;
+        match res {
+            Ok(info) => self.mint_info = Some(info),
+            Err(e) => {
+                self.error = Some(format!("mint {e}"));
+                return Err(self);
+            }
+        }
+        if let Err(e) = self.check_mint_extensions() {
+            self.error = Some(e.to_string());
+            return Err(self);
+        }
+        Ok(self)
+    }
+
+    fn check_mint_extensions(&self) -> anyhow::Result<()> {
+        let Some(info) = self.mint_info.as_ref().filter(|x| !x.data.is_empty()) else {
+            return Ok(());
+        };
+        let state = unpack_mint(&info.data)?;
+        for extension in state.get_extension_types()? {
+            match extension {
+                ExtensionType::PermanentDelegate
+                | ExtensionType::NonTransferable
+                | ExtensionType::TransferHook => {
+                    return Err(anyhow!("hostile mint extension {extension:?}"));
+                }
+                ExtensionType::DefaultAccountState => {
+                    let default = state.get_extension::<DefaultAccountState>()?;
+                    if default.state == AccountState::Frozen as u8 {
+                        return Err(anyhow!("mint freezes new token accounts"));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Token-2022 transfer fee on sending `amount` raw tokens, 0 for spl-token mints
+    pub fn transfer_fee(&self, amount: u64) -> u64 {
+        let Some(info) = self.mint_info.as_ref().filter(|x| !x.data.is_empty()) else {
+            return 0;
+        };
+        unpack_mint(&info.data).map_or(0, |state| get_transfer_fee(&state, current_epoch().max(info.epoch), amount))
+    }
+
+    /// Transfer fee that leaves `post_fee_amount` raw tokens with the receiver
+    pub fn transfer_inverse_fee(&self, post_fee_amount: u64) -> u64 {
+        let Some(info) = self.mint_info.as_ref().filter(|x| !x.data.is_empty()) else {
+            return 0;
+        };
+        unpack_mint(&info.data).map_or(0, |state| get_transfer_inverse_fee(&state, current_epoch().max(info.epoch), post_fee_amount))
+    }
+
+    /// State of a position that still holds tokens
+    fn held_state(&self) -> TradeState {
+        if self.fills.iter().any(|x| x.side == TradeFillSide::Sell) {
+            TradeState::PartiallyClosed
+        } else {
+            TradeState::BuySuccess
+        }
+    }
+
+    /// True when a failed sell should go out again with a bumped fee
+    pub fn sell_retry_due(&self) -> bool {
+        self.state == TradeState::SellFailed && !self.needs_attention && !self.migrating
+    }
+
+    /// True once a pending swap can no longer land, paper swaps never expire
+    pub fn blockhash_expired(&self, height: u64) -> bool {
+        !self.paper
+            && self.is_pending()
+            && self.last_valid_block_height.map_or(false, |x| height > x)
+    }
+
+    /// True when the pending swap was sent more than `cfg.rebroadcast_ms` ago
+    pub fn rebroadcast_due(&self, now: DateTime<Utc>) -> bool {
+        !self.paper
+            && self.is_pending()
+            && self.cfg.rebroadcast_ms > 0
+            && !self.transactions.is_empty()
+            && self.last_sent.map_or(false, |x| {
+                now - x >= chrono::Duration::milliseconds(self.cfg.rebroadcast_ms as i64)
+            })
+    }
+
+    /// True when the limit condition holds, always true for market trades
+    pub fn limit_reached(&self) -> bool {
+        match self.limit {
+            None => true,
+            Some(limit) => {
+                limit.price.map_or(false, |x| self.price <= x)
+                    || limit.tvl.map_or(false, |x| self.tvl >= x)
             }
-        }).collect::<Vec<_>>();
-        self.transactions = txs;
+        }
+    }
+
+    pub fn limit_expired(&self) -> bool {
+        self.limit.map_or(false, |x| Utc::now() > x.expiry)
+    }
+
+    /// Raises the high water mark used by the trailing stop, true when it moved
+    pub fn update_high_price(&mut self) -> bool {
+        if self.high_price.map_or(true, |x| self.price > x) {
+            self.high_price = Some(self.price);
+            return true;
+        }
+        false
+    }
+
+    /// Price the full exit fires below, the higher of the stop loss and the trailing stop
+    pub fn stop_price(&self) -> Option<Decimal> {
+        let one = dec!(1);
+        let sl = self.buy_price.map(|x| x * (one + self.cfg.sl));
+        let trailing = self
+            .high_price
+            .filter(|_| self.cfg.trailing_sl > Decimal::ZERO)
+            .map(|x| x * (one - self.cfg.trailing_sl));
+        sl.into_iter().chain(trailing).max()
+    }
+
+    /// Next `cfg.tp_ladder` level if the current pnl has reached it
+    pub fn tp_level_reached(&self) -> Option<usize> {
+        self.cfg
+            .tp_level(self.tp_level)
+            .filter(|x| self.pct >= x.pct)
+            .map(|_| self.tp_level)
+    }
+
+    /// Fraction of the tokens held to sell at the current price, 1 closes the position.
+    /// Stop loss and trailing stop close everything, then the next `tp_ladder` level,
+    /// then `tp` once the ladder is used up
+    pub fn exit_fraction(&self) -> Option<Decimal> {
+        let one = dec!(1);
+        if self.pct < self.cfg.sl {
+            return Some(one);
+        }
+        if self.cfg.trailing_sl > Decimal::ZERO {
+            if let Some(high) = self.high_price {
+                if self.price < high * (one - self.cfg.trailing_sl) {
+                    return Some(one);
+                }
+            }
+        }
+        if let Some(level) = self.cfg.tp_level(self.tp_level) {
+            return if self.pct >= level.pct {
+                Some(level.fraction)
+            } else {
+                None
+            };
+        }
+        if self.pct > self.cfg.tp {
+            Some(one)
+        } else {
+            None
+        }
+    }
+
+    /// Bundles this swap right behind the built jito transaction of `buy`,
+    /// both land in the same block or neither does
+    pub fn with_bundle_behind(mut self, buy: &Trade) -> Self {
+        if let Some(tx) = buy.transactions.iter().find(|x| matches!(x, TradeTransaction::Jito(_))) {
+            self.bundle_head = vec![tx.transaction().clone()];
+            self.cfg.jito_bundle = true;
+        }
         self
     }
+
+    /// Sets `sell_amount` to `fraction` of the tokens held
+    pub fn with_sell_fraction(self, fraction: Decimal) -> anyhow::Result<Self> {
+        let held = self.amount;
+        self.with_sell_tokens(held * fraction)
+    }
+
+    /// Fails when `tokens` rounds down to nothing, a 0 token sell would only leave the ATA open
+    fn with_sell_tokens(mut self, tokens: Decimal) -> anyhow::Result<Self> {
+        let tokens = tokens.floor();
+        if tokens <= Decimal::ZERO {
+            return Err(anyhow!("trade {} sell rounds down to 0 tokens", self.id));
+        }
+        self.sell_amount = if tokens >= self.amount {
+            None
+        } else {
+            tokens.to_u64()
+        };
+        Ok(self)
+    }
+
+    /// Tokens the next sell will spend
+    pub fn sell_amount(&self) -> u64 {
+        self.sell_amount.unwrap_or(self.amount.to_u64().unwrap())
+    }
+
+    /// Partial sells keep the token account open
+    pub fn is_partial_sell(&self) -> bool {
+        self.sell_amount.is_some()
+    }
+
+    /// Bought and not fully sold
+    pub fn is_open(&self) -> bool {
+        matches!(self.state, TradeState::BuySuccess | TradeState::PartiallyClosed)
+    }
+
+    pub fn push_fill(&mut self, side: TradeFillSide, signature: Signature, amount: Decimal, transfer_fee: Decimal) {
+        self.fills.push(TradeFill {
+            side,
+            signature: signature.to_string(),
+            time: Utc::now(),
+            amount,
+            price: self.price,
+            transfer_fee,
+        });
+    }
+
+    /// Total tokens from buy fills, `buy_out` for trades without fills
+    pub fn bought_amount(&self) -> Decimal {
+        let bought = self
+            .fills
+            .iter()
+            .filter(|x| x.side == TradeFillSide::Buy)
+            .map(|x| x.amount)
+            .sum::<Decimal>();
+        if bought.is_zero() {
+            Decimal::from(self.buy_out.unwrap_or_default())
+        } else {
+            bought
+        }
+    }
+
+    /// Walks the fills with average cost, returns (tokens held, average entry price, realized pnl in SOL)
+    fn position(&self) -> (Decimal, Decimal, Decimal) {
+        let unit = dec!(10).powd(Decimal::from(self.decimals()));
+        let mut held = Decimal::ZERO;
+        let mut avg = Decimal::ZERO;
+        let mut realized = Decimal::ZERO;
+        for fill in &self.fills {
+            match fill.side {
+                // a transfer fee raises the cost of what was received and lowers what a sale returns
+                TradeFillSide::Buy => {
+                    let total = held + fill.amount;
+                    if !total.is_zero() {
+                        avg = (avg * held + fill.price * (fill.amount + fill.transfer_fee)) / total;
+                    }
+                    held = total;
+                }
+                TradeFillSide::Sell => {
+                    realized += (fill.price * (fill.amount - fill.transfer_fee) - avg * fill.amount) / unit;
+                    held -= fill.amount;
+                }
+            }
+        }
+        (held, avg, realized)
+    }
+
+    /// Volume weighted entry price of the tokens still held
+    pub fn avg_entry_price(&self) -> Option<Decimal> {
+        let (_, avg, _) = self.position();
+        if avg.is_zero() {
+            self.buy_price
+        } else {
+            Some(avg)
+        }
+    }
+
+    /// SOL in the held tokens at cost, plus what a buy in flight may spend
+    pub fn exposure(&self) -> Decimal {
+        if matches!(self.state, TradeState::BuyFailed | TradeState::SellSuccess) {
+            return Decimal::ZERO;
+        }
+        let unit = dec!(10).powd(Decimal::from(self.decimals()));
+        let cost = self.avg_entry_price().unwrap_or_default() * self.amount / unit;
+        if self.is_buy() {
+            cost + self.buy_sol()
+        } else {
+            cost
+        }
+    }
+
+    /// SOL made or lost on sold tokens
+    pub fn realized_pnl(&self) -> Decimal {
+        self.position().2
+    }
+
+    /// SOL made or lost on held tokens if they were all sold now, see [Trade::exit_price]
+    pub fn unrealized_pnl(&self) -> Decimal {
+        let unit = dec!(10).powd(Decimal::from(self.decimals()));
+        let fee = Decimal::from(self.transfer_fee(self.amount.to_u64().unwrap_or_default()));
+        match self.avg_entry_price() {
+            Some(avg) => (self.exit_price() * (self.amount - fee) - avg * self.amount) / unit,
+            None => Decimal::ZERO,
+        }
+    }
+
+    /// Change from the buy price to the spot price, what the strategies' levels are set in.
+    /// [Trade::unrealized_pnl] is the one with fees and price impact.
+    pub fn update_pct(&mut self) {
+        if let Some(buy_price) = self.buy_price {
+            self.pct = (self.price - buy_price) / buy_price;
+        }
+    }
+
+    /// Lands the pending buy, `amount_out` is raw tokens sent by the pool before any transfer fee
+    pub fn apply_buy_fill(&mut self, signature: Signature, amount_out: u64) -> anyhow::Result<()> {
+        self.transition(self.held_state(), "buy landed")?;
+        let fee = self.transfer_fee(amount_out);
+        let amount_out = amount_out - fee;
+        self.push_fill(TradeFillSide::Buy, signature, Decimal::from(amount_out), Decimal::from(fee));
+        self.buy_out = self.bought_amount().to_u64();
+        self.buy_time.get_or_insert(Utc::now());
+        self.buy_price = self.avg_entry_price();
+        self.amount += Decimal::from(amount_out);
+        self.scale_in_sol = None;
+        // signed for the old position
+        self.presigned_exits.clear();
+        for x in self.rpc_logs.iter_mut() {
+            x.change_state(signature, TradeRpcLogStatus::Success);
+        }
+        self.retries = 0;
+        Ok(())
+    }
+
+    /// Lands the pending sell, a partial sell keeps the trade open
+    pub fn apply_sell_fill(&mut self, signature: Signature) -> anyhow::Result<()> {
+        if self.is_partial_sell() {
+            self.transition(TradeState::PartiallyClosed, "partial sell landed")?;
+            let sold = Decimal::from(self.sell_amount());
+            let fee = Decimal::from(self.transfer_fee(self.sell_amount()));
+            self.push_fill(TradeFillSide::Sell, signature, sold, fee);
+            self.amount -= sold;
+            self.sell_amount = None;
+            if self.sell_tp_level.take().is_some() {
+                self.tp_level += 1;
+            }
+        } else {
+            self.transition(TradeState::SellSuccess, "sell landed")?;
+            let fee = Decimal::from(self.transfer_fee(self.sell_amount()));
+            self.push_fill(TradeFillSide::Sell, signature, self.amount, fee);
+            self.amount = Decimal::ZERO;
+            self.sell_time = Some(Utc::now());
+            self.sell_price = Some(self.price);
+        }
+        self.presigned_exits.clear();
+        self.nonce_in_flight = None;
+        self.retries = 0;
+        Ok(())
+    }
+
     pub fn is_buy(&self)->bool{
         match self.state {
             TradeState::Buy|TradeState::PendingBuy => true,
@@ -329,6 +1146,9 @@ impl Trade {
             TradePool::RayAmm4(_) => 6,
             TradePool::PumpBondingCurve(z) => 6,
             TradePool::Generic(_) => 6,
+            TradePool::RayCpmm(z) => z.decimals.unwrap_or(6),
+            TradePool::RayClmm(z) => z.decimals.unwrap_or(6),
+            TradePool::PumpAmm(z) => z.decimals.unwrap_or(6),
             _ => unreachable!()
         }
     }
@@ -344,6 +1164,15 @@ impl Trade {
             }
             TradePool::Generic(_) => {
 
+            }
+            TradePool::RayCpmm(ref mut z) => {
+                z.decimals = Some(decimals);
+            }
+            TradePool::RayClmm(ref mut z) => {
+                z.decimals = Some(decimals);
+            }
+            TradePool::PumpAmm(ref mut z) => {
+                z.decimals = Some(decimals);
             }
             x => {
                 info!("{:?}",x);
@@ -352,8 +1181,18 @@ impl Trade {
         }
     }
 
-    pub fn update_price_from_log(mut self, log:String, next:bool) -> anyhow::Result<Self> {
+    pub fn update_price_from_log(self, log:String, next:bool) -> anyhow::Result<Self> {
         let plog = ProgramLog::from(log.clone())?;
+        self.update_price_from_plog(plog, next)
+    }
+
+    pub fn update_price_from_plog(mut self, plog:ProgramLog, next:bool) -> anyhow::Result<Self> {
+        // a mint can have several pools, only ours moves our price
+        if let (Some(pool), true) = (plog.pool(), self.has_pool()) {
+            if pool != self.amm() {
+                return Err(anyhow!("log for pool {pool}, trade {} is on {}", self.id, self.amm()));
+            }
+        }
         let (pc,coin) = match &plog.log {
             ProgramLogInfo::RayInitLog(init) => {
                 self.update_decimals(
@@ -383,13 +1222,56 @@ impl Trade {
                 self.update_decimals(6);
                 (plog.next_pc, plog.next_coin)
             }
+            ProgramLogInfo::RayCpSwap(_) => {
+                if next {
+                    (plog.next_pc, plog.next_coin)
+                } else {
+                    (plog.pc, plog.coin)
+                }
+            }
+            ProgramLogInfo::RayClmmSwap(swap) => {
+                if let TradePool::RayClmm(ref mut z) = self.amm {
+                    z.tick_current = Some(swap.tick);
+                }
+                (plog.next_pc, plog.next_coin)
+            }
+            ProgramLogInfo::PumpAmmBuy(_) | ProgramLogInfo::PumpAmmSell(_) => {
+                if let TradePool::PumpAmm(ref mut z) = self.amm {
+                    z.fee_bps = Some(match &plog.log {
+                        ProgramLogInfo::PumpAmmBuy(x) => {
+                            x.lp_fee_basis_points + x.protocol_fee_basis_points + x.coin_creator_fee_basis_points
+                        }
+                        ProgramLogInfo::PumpAmmSell(x) => {
+                            x.lp_fee_basis_points + x.protocol_fee_basis_points + x.coin_creator_fee_basis_points
+                        }
+                        _ => unreachable!()
+                    });
+                }
+                if next {
+                    (plog.next_pc, plog.next_coin)
+                } else {
+                    (plog.pc, plog.coin)
+                }
+            }
             _ => {
-                return Err(anyhow!("known program, unknown event\nlog {log}\nplog: {plog:?}"));
+                return Err(anyhow!("known program, unknown event\nplog: {plog:?}"));
             },
         };
 
-        let sol_amount = min(pc, coin);
-        let token_amount = max(pc, coin);
+        let (sol_amount, token_amount) = match self.amm {
+            // sorted pools, token 0 is the coin side
+            TradePool::RayCpmm(RayCpmm { token_0_mint, .. })
+            | TradePool::RayClmm(RayClmm { token_0_mint, .. }) => {
+                if token_0_mint == SOLANA_MINT {
+                    (coin, pc)
+                } else {
+                    (pc, coin)
+                }
+            }
+            // quote is always SOL, see `with_pump_amm_swap`
+            TradePool::PumpAmm(_) => (pc, coin),
+            _ => (min(pc, coin), max(pc, coin)),
+        };
 
         // 1000
         let token_amount_ui = Decimal::from(token_amount) / dec!(10).powd(Decimal::from(self.decimals()));
@@ -436,6 +1318,15 @@ impl Trade {
             }
             TradePool::Generic(mint) => mint,
             TradePool::PumpBondingCurveMint(mint) => mint,
+            TradePool::RayCpmm(RayCpmm { token_0_mint, token_1_mint, .. })
+            | TradePool::RayClmm(RayClmm { token_0_mint, token_1_mint, .. }) => {
+                if token_0_mint == SOLANA_MINT {
+                    token_1_mint
+                } else {
+                    token_0_mint
+                }
+            }
+            TradePool::PumpAmm(z) => z.base_mint,
         }
 
     }
@@ -443,12 +1334,31 @@ impl Trade {
         Keypair::from_bytes(&self.root_kp).unwrap()
     }
     pub fn token_program(&self)->Pubkey{
+        if let Some(info) = &self.mint_info {
+            return info.program;
+        }
         match self.amm {
             TradePool::RayAmm4(z) => z.token_program_id,
             TradePool::PumpBondingCurve(z) => z.token_program_id,
+            TradePool::RayCpmm(z) if z.token_0_mint == SOLANA_MINT => z.token_1_program,
+            TradePool::RayCpmm(z) => z.token_0_program,
+            // until `load_mint` reads the mint
+            TradePool::RayClmm(_) => spl_token::id(),
+            TradePool::PumpAmm(z) => z.base_token_program,
             _ => unreachable!()
         }
     }
+    /// True once the trade knows its pool, the root trade and mint-only trades don't
+    pub fn has_pool(&self) -> bool {
+        matches!(
+            self.amm,
+            TradePool::RayAmm4(_)
+                | TradePool::PumpBondingCurve(_)
+                | TradePool::RayCpmm(_)
+                | TradePool::RayClmm(_)
+                | TradePool::PumpAmm(_)
+        )
+    }
     pub fn amm(&self)->Pubkey{
         self.amm.amm()
     }
@@ -493,11 +1403,63 @@ impl Trade {
         }
     }
     pub async fn send_many(mut self, rpcs: Vec<TradeRpc>)->FuturesUnordered<JoinHandle<Result<Trade,Trade>>>{
+        let loaded = self.mint_info.is_some();
+        let pool_loaded = self.pool_loaded();
+        self = match self.load_pool().await {
+            Ok(trade) => trade,
+            Err(trade) => {
+                error!("{:?}", trade.error);
+                return trade.rejected(&rpcs);
+            }
+        };
+        self = match self.load_mint().await {
+            Ok(trade) => trade,
+            Err(trade) => {
+                error!("{:?}", trade.error);
+                return trade.rejected(&rpcs);
+            }
+        };
+        // the mint's program and fees or the pool's decimals just became known, or the CLMM pool was read again
+        if (!loaded && self.mint_info.is_some())
+            || (!pool_loaded && self.pool_loaded())
+            || matches!(self.amm, TradePool::RayClmm(_))
+        {
+            self = self.rebuild_instructions();
+        }
+        // paper trades only need the instructions
+        let trade = if self.paper {
+            Ok(self)
+        } else if self.cfg.simulate {
+            match self.preflight().await {
+                Ok(trade) => trade.build_transactions().await,
+                Err(trade) => return trade.rejected(&rpcs),
+            }
+        } else {
+            self.build_transactions().await
+        };
+        match trade {
+            Ok(trade) => trade.broadcast(rpcs),
+            Err(trade) => {
+                error!("{:?}", trade.error);
+                trade.rejected(&rpcs)
+            }
+        }
+    }
+    /// Every rpc answers once with the error, the swap never went out
+    fn rejected(self, rpcs: &[TradeRpc])->FuturesUnordered<JoinHandle<Result<Trade,Trade>>>{
+        let futs= FuturesUnordered::new();
+        for _ in rpcs {
+            let trade = self.clone();
+            futs.push(tokio::spawn(async move { Err(trade) }));
+        }
+        futs
+    }
+    /// Sends the built transactions as they are, signatures stay the same
+    pub fn broadcast(&self, rpcs: Vec<TradeRpc>)->FuturesUnordered<JoinHandle<Result<Trade,Trade>>>{
         let futs= FuturesUnordered::new();
-        let trade = self.build_transactions().await;
         for rpc in rpcs {
             futs.push(tokio::spawn({
-                let trade = trade.clone();
+                let trade = self.clone();
                 async move {
                     trade.send(rpc).await
                 }
@@ -505,6 +1467,55 @@ impl Trade {
         }
         futs
     }
+    /// Simulates the swap with a fresh blockhash and sizes `cfg.cu_limit` from the consumed units.
+    /// Fails like `send` when the simulation fails, keeps the fixed gas limits when the rpc is down.
+    pub async fn preflight(mut self) -> Result<Trade,Trade> {
+        // a sell bundled behind a buy spends tokens the buy hasn't brought yet, alone it always fails
+        if !self.bundle_head.is_empty() {
+            return Ok(self);
+        }
+        self.cfg.cu_limit = Decimal::ZERO;
+        self = self.rebuild_instructions();
+        let ix = self.instructions.iter()
+            .find(|x|matches!(x, TradeInstruction::Jito(_)))
+            .or_else(||self.instructions.first())
+            .unwrap().instructions().clone();
+        // the same message kind `sign_transactions` sends, unsigned
+        let tx = match self.compile_message(&ix, Hash::default()) {
+            Ok(message) => VersionedTransaction {
+                signatures: vec![Signature::default(); message.header().num_required_signatures as usize],
+                message,
+            },
+            Err(e) => {
+                self.error = Some(e.to_string());
+                return Err(self);
+            }
+        };
+        let res = solana_rpc_client()
+            .simulate_transaction_with_config(&tx, solana_client::rpc_config::RpcSimulateTransactionConfig {
+                sig_verify: false,
+                replace_recent_blockhash: true,
+                commitment: Some(solana_sdk::commitment_config::CommitmentConfig::processed()),
+                ..Default::default()
+            })
+            .await;
+        let sim = match res {
+            Ok(x) => x.value,
+            Err(e) => {
+                error!("simulation rpc {e}");
+                return Ok(self);
+            }
+        };
+        if let Some(err) = sim.err {
+            self.error = Some(simulation_error(&err.to_string(), &sim.logs.unwrap_or_default()));
+            return Err(self);
+        }
+        if let Some(units) = sim.units_consumed {
+            self.cfg.cu_limit = (Decimal::from(units) * (dec!(1) + self.cfg.cu_margin)).ceil();
+            info!("simulated {units} cu, limit {}", self.cfg.cu_limit);
+        }
+        Ok(self.rebuild_instructions())
+    }
     pub async fn send(mut self, rpc:TradeRpc) -> Result<Trade,Trade> {
         let start_time = ::std::time::Instant::now();
         let mut log_info = None;
@@ -513,23 +1524,15 @@ impl Trade {
             TradeRpc::Jito { rpc,info,.. } => {
                 let mut trade = self.clone();
 
-                info!("before you send. just tell me if you are buy {}",trade.is_buy());
-                if !trade.is_buy() {
-                    info!("BIG BUG {:?}",trade);
-                    info!("BIG BUG {:?}",trade.instructions);
-                }
                 let tx = trade.transactions.iter()
                     .find(|&x|matches!(x, TradeTransaction::Jito(_)))
                     .unwrap().transaction().clone();
                 let ix = trade.instructions.iter()
                     .find(|x|matches!(x, TradeInstruction::Jito(_)))
                     .unwrap().instructions().clone();
-                let serialized_tx =
-                    base64::engine::general_purpose::STANDARD.encode(bincode::serialize(&tx).unwrap());
-                let params = json!({
-                    "tx": serialized_tx,
-                    "skipPreflight": true
-                });
+                let tip_tx = trade.transactions.iter()
+                    .find(|&x|matches!(x, TradeTransaction::JitoTip(_)))
+                    .map(|x|x.transaction().clone());
                 // if self.is_buy() == false {
                 //     if let TradeRpc::General {rpc,..} = rpc1() {
                 //         info!("what is going on");
@@ -544,10 +1547,40 @@ impl Trade {
                 //     }
                 // }
 
-                let resp = rpc.send_txn(Some(params), true).await.unwrap();
-                let sig = resp["result"]
-                    .as_str()
-                    .ok_or_else(|| anyhow::format_err!("Failed to get signature from response"));
+                let mut bundle_id = None;
+                let sig = if let Some(tip_tx) = tip_tx {
+                    // swap and tip land together or not at all
+                    let bundle = trade.bundle_head.iter()
+                        .chain([&tx, &tip_tx])
+                        .map(|x|bs58::encode(bincode::serialize(x).unwrap()).into_string())
+                        .collect::<Vec<_>>();
+                    rpc.send_bundle(Some(json!(bundle)), None)
+                        .await
+                        .map_err(|e| anyhow::format_err!("send bundle {e}"))
+                        .and_then(|resp| {
+                            bundle_id = resp["result"].as_str().map(|x|x.to_string());
+                            bundle_id
+                                .as_ref()
+                                .map(|_|tx.signatures[0].to_string())
+                                .ok_or_else(|| anyhow::format_err!("Failed to get bundle id from response {resp}"))
+                        })
+                } else {
+                    let serialized_tx =
+                        base64::engine::general_purpose::STANDARD.encode(bincode::serialize(&tx).unwrap());
+                    let params = json!({
+                        "tx": serialized_tx,
+                        "skipPreflight": true
+                    });
+                    rpc.send_txn(Some(params), true)
+                        .await
+                        .map_err(|e| anyhow::format_err!("send txn {e}"))
+                        .and_then(|resp| {
+                            resp["result"]
+                                .as_str()
+                                .map(|x|x.to_string())
+                                .ok_or_else(|| anyhow::format_err!("Failed to get signature from response"))
+                        })
+                };
                 log_info = Some(info.clone());
                 info!("{} {} Trade sent tx: {:?}",info.name, info.location, start_time.elapsed());
                 let t = self.clone();
@@ -566,12 +1599,13 @@ impl Trade {
                     let log = TradeRpcLogJito{
                         general,
                         tip_sol_ui: trade.jito_fee_sol_ui().to_string(),
+                        tip_policy: trade.cfg.jito_tip_policy,
+                        bundle_id: bundle_id.clone(),
+                        bundle_status: None,
                     };
                     if trade.is_buy() {
-                        trade.state = TradeState::PendingBuy;
                         trade.push_rpc_logs(TradeRpcLog::BuyJito(log,TradeRpcLogStatus::Pending));
                     } else {
-                        trade.state = TradeState::PendingSell;
                         trade.push_rpc_logs(TradeRpcLog::SellJito(log,TradeRpcLogStatus::Pending));
                     }
                     trade
@@ -609,10 +1643,8 @@ impl Trade {
                             ix: ix.clone()
                         };
                         if trade.is_buy() {
-                            trade.state = TradeState::PendingBuy;
                             trade.push_rpc_logs(TradeRpcLog::BuyGeneral(log,TradeRpcLogStatus::Pending));
                         } else {
-                            trade.state = TradeState::PendingSell;
                             trade.push_rpc_logs(TradeRpcLog::SellGeneral(log,TradeRpcLogStatus::Pending));
                         }
                         trade
@@ -620,6 +1652,25 @@ impl Trade {
                     .map_err(anyhow::Error::from);
                 resp
             }
+            TradeRpc::Paper { info } => {
+                let mut trade = self.clone();
+                let ix = trade.instructions.iter()
+                    .find(|x|matches!(x, TradeInstruction::General(_)))
+                    .unwrap().instructions().clone();
+                let log = TradeRpcLogGeneral{
+                    signature: Signature::new_unique().to_string(),
+                    response_time: format!("{:?}", start_time.elapsed()),
+                    name: format!("{}",info.name),
+                    tx: Transaction::new_with_payer(&ix, Some(&trade.root_kp().pubkey())).into(),
+                    ix,
+                };
+                if trade.is_buy() {
+                    trade.push_rpc_logs(TradeRpcLog::BuyGeneral(log,TradeRpcLogStatus::Pending));
+                } else {
+                    trade.push_rpc_logs(TradeRpcLog::SellGeneral(log,TradeRpcLogStatus::Pending));
+                }
+                Ok(trade)
+            }
         }
             .map(|mut x|{
                 // x.transactions = Default::default();
@@ -629,12 +1680,8 @@ impl Trade {
             .map_err(|x|{
                 // self.transactions = Default::default();
                 // self.instructions = Default::default();
+                // the engine settles the state once every rpc failed, see `Trade::fail_swap`
                 self.error = Some(x.to_string());
-                if self.is_buy() {
-                    self.state = TradeState::BuyFailed;
-                } else {
-                    self.state = TradeState::SellFailed;
-                }
                 self.clone()
             });
         info!("Trade sent tx: {:?}", start_time.elapsed());
@@ -646,6 +1693,7 @@ impl Trade {
 
         let one = dec!(1);
         let gas_limit = match self.amm {
+            _ if self.cfg.cu_limit > Decimal::ZERO => self.cfg.cu_limit.to_u64().unwrap(),
             TradePool::RayAmm4(_)|TradePool::Generic(_) => {
                 if self.is_buy() {
                     self.cfg.ray_buy_gas_limit
@@ -653,13 +1701,21 @@ impl Trade {
                     self.cfg.ray_sell_gas_limit
                 }
             }
-            TradePool::PumpBondingCurve(_)|TradePool::PumpBondingCurveMint(_) => {
+            TradePool::PumpBondingCurve(_)|TradePool::PumpBondingCurveMint(_)|TradePool::PumpAmm(_) => {
                 if self.is_buy() {
                     self.cfg.pump_buy_gas_limit
                 } else {
                     self.cfg.pump_sell_gas_limit
                 }
             }
+            TradePool::RayCpmm(_) => {
+                if self.is_buy() {
+                    self.cfg.ray_buy_gas_limit
+                } else {
+                    self.cfg.ray_sell_gas_limit
+                }
+            }
+            TradePool::RayClmm(_) => self.cfg.ray_clmm_gas_limit.max(self.cfg.ray_buy_gas_limit),
         };
 
         assert!(gas_limit.to_u64().unwrap() > 0);
@@ -668,10 +1724,11 @@ impl Trade {
         let kp = self.root_kp();
         let pubkey = kp.pubkey();
         let amm = self.amm();
+        // wrapped SOL is always spl-token
         let wsol = get_associated_token_address_with_program_id(
             &pubkey,
             &self.sol_mint(),
-            &token_program_id,
+            &spl_token::id(),
         );
         let token = get_associated_token_address_with_program_id(
             &pubkey,
@@ -684,19 +1741,50 @@ impl Trade {
         // amount higher than slippage
         // 1. got exact price
         let decimals = self.decimals();
-        let max_in_token_ui = cfg.max_sol / self.price;
-        let max_in_sol = cfg.max_sol * lamports_per_sol_dec();
-
-        let max_in_token = max_in_token_ui * dec!(10).powd(Decimal::from(decimals));
+        let max_in_token_ui = self.buy_sol() / self.price;
+        let max_in_sol = self.buy_sol() * lamports_per_sol_dec();
 
+        // what max_sol buys against the pool's reserves, spot for pools without them
+        let max_in_token = match self.quote(max_in_sol.to_u64().unwrap(), true) {
+            Some(quote) => {
+                info!("quote {quote:?}");
+                Decimal::from(quote.amount_out)
+            }
+            None => max_in_token_ui * dec!(10).powd(Decimal::from(decimals)),
+        };
 
         let min_out_token = max_in_token * (dec!(1)-cfg.slippage);
+        // Token-2022 withholds part of what the pool sends
+        let min_out_token = min_out_token - Decimal::from(self.transfer_fee(min_out_token.to_u64().unwrap_or_default()));
+
+        // exact out buys pay what `min_out_token` costs plus slippage, never more than buy_sol
+        let max_in_sol_exact_out = match self.quote_out(min_out_token.to_u64().unwrap_or_default(), true) {
+            Some(quote) => max_in_sol.min((Decimal::from(quote.amount_in) * (dec!(1) + cfg.slippage)).ceil()),
+            None => max_in_sol,
+        };
+        // what the sell gets against the pool's reserves less slippage, any price for pools without them
+        let min_out_sol = if self.is_buy() {
+            0
+        } else {
+            let sell_amount = self.sell_amount();
+            self.quote(sell_amount - self.transfer_fee(sell_amount), false)
+                .map(|x| Decimal::from(x.amount_out))
+                // a presigned exit lands at its trigger price, not the current one
+                .map(|x| match self.sell_trigger_price {
+                    Some(trigger) if self.price > Decimal::ZERO => x * trigger / self.price,
+                    _ => x,
+                })
+                .and_then(|x| (x * (dec!(1) - cfg.slippage)).to_u64())
+                .unwrap_or_default()
+        };
 
         info!("min_out_token {min_out_token}");
+        info!("min_out_sol {min_out_sol}");
         info!("max_in_token_ui {max_in_token_ui}");
         info!("max_in_sol {max_in_sol}");
+        info!("max_in_sol_exact_out {max_in_sol_exact_out}");
         info!("max_in_token {max_in_token}");
-        info!("max_sol {}", cfg.max_sol);
+        info!("buy_sol {}", self.buy_sol());
         info!("price {}", self.price);
 
         let mut instructions = vec![
@@ -742,7 +1830,7 @@ impl Trade {
                             &wsol,
                             &token,
                             &pubkey,
-                            max_in_token.to_u64().unwrap(),
+                            max_in_sol_exact_out.to_u64().unwrap(),
                             min_out_token.to_u64().unwrap(),
                         )
                             .unwrap(),
@@ -767,32 +1855,36 @@ impl Trade {
                             &token,
                             &wsol,
                             &kp.pubkey(),
-                            self.amount.to_u64().unwrap(),
-                            Default::default() // slippage default 0
-                        )
-                            .unwrap(),
-                        spl_token::instruction::close_account(
-                            &token_program_id,
-                            &token,
-                            &kp.pubkey(),
-                            &kp.pubkey(),
-                            &[&kp.pubkey()],
+                            self.sell_amount(),
+                            min_out_sol,
                         )
                             .unwrap(),
                         // build_memo_instruction(),
                     ]);
+                    if !self.is_partial_sell() {
+                        instructions.push(
+                            spl_token_2022::instruction::close_account(
+                                &token_program_id,
+                                &token,
+                                &kp.pubkey(),
+                                &kp.pubkey(),
+                                &[&kp.pubkey()],
+                            )
+                                .unwrap(),
+                        );
+                    }
                 }
             }
             TradePool::PumpBondingCurve(curve) => {
                 let min = if self.is_buy() {
                     min_out_token.to_u64().unwrap()
                 } else {
-                    Default::default()
+                    min_out_sol
                 };
                 let max = if self.is_buy() {
-                    max_in_sol.to_u64().unwrap()
+                    max_in_sol_exact_out.to_u64().unwrap()
                 } else {
-                    self.buy_out.unwrap()
+                    self.sell_amount()
                 };
                 let pump_ix = pump_swap(
                     PumpTrade {
@@ -805,11 +1897,12 @@ impl Trade {
                         ata_curve: curve.vault,
                         ata_user: token,
                         user: self.root_kp().pubkey(),
+                        token_program: token_program_id,
                     },
                     self.is_buy()
                 ).unwrap();
 
-                let a = spl_token::instruction::close_account(
+                let a = spl_token_2022::instruction::close_account(
                     &token_program_id,
                     &token,
                     &kp.pubkey(),
@@ -820,21 +1913,79 @@ impl Trade {
                 instructions.extend_from_slice(&vec![
                     pump_ix,
                 ]);
-                if !self.is_buy() {
+                if !self.is_buy() && !self.is_partial_sell() {
                     instructions.push(a);
                 }
             }
+            TradePool::RayCpmm(_) | TradePool::RayClmm(_) => {
+                let (amount_in, min_out) = if self.is_buy() {
+                    (max_in_sol.to_u64().unwrap(), min_out_token.to_u64().unwrap())
+                } else {
+                    (self.sell_amount(), min_out_sol)
+                };
+                let swap_ix = match self.amm {
+                    TradePool::RayCpmm(pool) => {
+                        ray_cp_swap(&pool, &pubkey, &wsol, &token, self.is_buy(), amount_in, min_out)
+                    }
+                    TradePool::RayClmm(pool) => {
+                        ray_clmm_swap(&pool, &pubkey, &wsol, &token, self.is_buy(), amount_in, min_out)
+                    }
+                    _ => unreachable!()
+                };
+                instructions.extend(wrap_sol(&pubkey, &wsol, if self.is_buy() { amount_in } else { 0 }));
+                instructions.push(swap_ix);
+                instructions.push(unwrap_sol(&pubkey, &wsol));
+                if !self.is_buy() && !self.is_partial_sell() {
+                    instructions.push(
+                        spl_token_2022::instruction::close_account(
+                            &token_program_id,
+                            &token,
+                            &pubkey,
+                            &pubkey,
+                            &[&pubkey],
+                        )
+                            .unwrap(),
+                    );
+                }
+            }
+            TradePool::PumpAmm(pool) => {
+                let (base_amount, quote_amount) = if self.is_buy() {
+                    (min_out_token.to_u64().unwrap(), max_in_sol_exact_out.to_u64().unwrap())
+                } else {
+                    (self.sell_amount(), min_out_sol)
+                };
+                // the buy takes at most `quote_amount`, the rest comes back with the close
+                instructions.extend(wrap_sol(&pubkey, &wsol, if self.is_buy() { quote_amount } else { 0 }));
+                instructions.push(pump_amm_swap(&pool, &pubkey, &wsol, &token, self.is_buy(), base_amount, quote_amount));
+                instructions.push(unwrap_sol(&pubkey, &wsol));
+                if !self.is_buy() && !self.is_partial_sell() {
+                    instructions.push(
+                        spl_token_2022::instruction::close_account(
+                            &token_program_id,
+                            &token,
+                            &pubkey,
+                            &pubkey,
+                            &[&pubkey],
+                        )
+                            .unwrap(),
+                    );
+                }
+            }
             _ => unreachable!()
         }
+        // the presigned sell that went out before can't land after this one
+        if let (Some(nonce), false) = (self.nonce_in_flight, self.is_buy()) {
+            instructions.insert(0, solana_sdk::system_instruction::advance_nonce_account(&nonce, &pubkey));
+        }
 
 
         vec![RpcType::Jito,RpcType::General].iter().for_each(|x|{
             match x {
                 RpcType::Jito => {
-                    let jito_fee_sol_normal = (self.cfg.jito_tip_percent / dec!(2)) * self.cfg.fee();
+                    let jito_fee_sol_normal = self.jito_fee_sol_ui();
                     let jito_fee_sol = jito_fee_sol_normal * lamports_per_sol_dec();
 
-                    let pfee_sol_normal = (self.cfg.jito_priority_percent / dec!(2)) * self.cfg.fee();
+                    let pfee_sol_normal = (self.cfg.jito_priority_percent / dec!(2)) * self.fee_sol_ui();
                     let pfee_micro_sol = pfee_sol_normal * micro_lamports_per_sol_dec();
 
 
@@ -846,19 +1997,22 @@ impl Trade {
                     info!("gas limit {}",gas_limit);
                     info!("gas price {}",gas_price);
                     info!("jito ix value {}",jito_fee_sol.to_u64().unwrap());
-                    let jito_instructions = vec![
+                    let tip_ix = solana_sdk::system_instruction::transfer(
+                        &self.root_kp().pubkey(),
+                        &Pubkey::from_str_const(crate::constant::JITO_TIPS[fastrand::usize(..crate::constant::JITO_TIPS.len())]),
+                        jito_fee_sol.to_u64().unwrap(),
+                    );
+                    let mut jito_instructions = vec![
                         &vec![
                             ComputeBudgetInstruction::set_compute_unit_price(gas_price.to_u64().unwrap())
                         ][..],
                         &instructions[..],
-                        &vec![
-                            solana_sdk::system_instruction::transfer(
-                                &self.root_kp().pubkey(),
-                                &Pubkey::from_str_const(crate::constant::JITO_TIPS[fastrand::usize(..crate::constant::JITO_TIPS.len())]),
-                                jito_fee_sol.to_u64().unwrap(),
-                            ),
-                        ][..]
                     ].concat();
+                    if self.cfg.jito_bundle {
+                        self.push_instructions(TradeInstruction::JitoTip(vec![tip_ix]));
+                    } else {
+                        jito_instructions.push(tip_ix);
+                    }
                     self.push_instructions(TradeInstruction::Jito(jito_instructions));
                 }
                 RpcType::General => {
@@ -896,6 +2050,128 @@ impl Trade {
         )
     }
 
+    /// Moves a curve trade to the Raydium pool its coin migrated to, keeps the position as is
+    pub fn migrate_to_raydium(self, accounts: &[Pubkey], log: String) -> anyhow::Result<Self> {
+        // anyone can open a pool for the mint, only pump's own is where the curve went
+        if accounts.get(17) != Some(&PUMP_MIGRATION) {
+            return Err(anyhow!("pool not opened by the pump migration {:?}", accounts.get(17)));
+        }
+        let mint = self.mint();
+        let cfg = self.cfg;
+        self.with_raydium_init(accounts, cfg, log)?.migrated(mint)
+    }
+
+    /// Moves a curve trade to the PumpSwap pool its coin graduated to, from the pool's first swap
+    pub fn migrate_to_pump_amm(self, accounts: &[Pubkey], log: String) -> anyhow::Result<Self> {
+        let mint = self.mint();
+        let trade = self.with_pump_amm_swap(accounts, log)?;
+        // anyone can open a pool for the mint, the curve's liquidity went to this one
+        let canonical = pump_amm_canonical_pool(&mint);
+        if trade.amm() != canonical {
+            return Err(anyhow!("pool {} is not the canonical {canonical}", trade.amm()));
+        }
+        trade.migrated(mint)
+    }
+
+    fn migrated(mut self, mint: Pubkey) -> anyhow::Result<Self> {
+        if self.mint() != mint {
+            return Err(anyhow!("pool {:?} is not for {mint}", self.amm));
+        }
+        self.migrating = false;
+        // signed for the curve
+        self.presigned_exits.clear();
+        Ok(self)
+    }
+
+    /// From a PumpSwap `buy` or `sell`, both take the same accounts
+    pub fn with_pump_amm_swap(self, accounts: &[Pubkey], log: String) -> anyhow::Result<Self> {
+        let [pool, payer, _, base_mint, quote_mint, _, _, pool_base_token_account, pool_quote_token_account, protocol_fee_recipient, _, base_token_program, ..] = *accounts else {
+            return Err(anyhow!("pump amm swap with {} accounts", accounts.len()));
+        };
+        if quote_mint != SOLANA_MINT {
+            return Err(anyhow!("not a token/SOL pool {pool}"));
+        }
+        self.try_new(
+            NewTrade{
+                log,
+                amm:TradePool::PumpAmm(PumpAmm{
+                    pool,
+                    base_mint,
+                    pool_base_token_account,
+                    pool_quote_token_account,
+                    base_token_program,
+                    protocol_fee_recipient,
+                    // swaps from before creator fees don't pass it
+                    coin_creator_vault_authority: accounts.get(18).copied(),
+                    decimals: None,
+                    fee_bps: None,
+                }),
+                user_wallet: payer,
+            }
+        )
+    }
+
+    /// From a CP-Swap `swap_base_input` or `swap_base_output`, both take the same accounts
+    pub fn with_ray_cp_swap(self, accounts: &[Pubkey], log: String) -> anyhow::Result<Self> {
+        let payer = accounts[0];
+        let (input, output) = ((accounts[6], accounts[8], accounts[10]), (accounts[7], accounts[9], accounts[11]));
+        // vault, program, mint
+        let (token_0, token_1) = if input.2 < output.2 { (input, output) } else { (output, input) };
+        if token_0.2 != SOLANA_MINT && token_1.2 != SOLANA_MINT {
+            return Err(anyhow!("not a SOL pool {}", accounts[3]));
+        }
+        self.try_new(
+            NewTrade{
+                log,
+                amm:TradePool::RayCpmm(RayCpmm{
+                    pool: accounts[3],
+                    amm_config: accounts[2],
+                    observation: accounts[12],
+                    token_0_mint: token_0.2,
+                    token_1_mint: token_1.2,
+                    token_0_vault: token_0.0,
+                    token_1_vault: token_1.0,
+                    token_0_program: token_0.1,
+                    token_1_program: token_1.1,
+                    decimals: None,
+                    trade_fee_rate: None,
+                }),
+                user_wallet: payer,
+            }
+        )
+    }
+
+    /// From a CLMM `swap_v2`
+    pub fn with_ray_clmm_swap(self, accounts: &[Pubkey], log: String) -> anyhow::Result<Self> {
+        let payer = accounts[0];
+        // vault, mint
+        let (input, output) = ((accounts[5], accounts[11]), (accounts[6], accounts[12]));
+        let (token_0, token_1) = if input.1 < output.1 { (input, output) } else { (output, input) };
+        if token_0.1 != SOLANA_MINT && token_1.1 != SOLANA_MINT {
+            return Err(anyhow!("not a SOL pool {}", accounts[2]));
+        }
+        self.try_new(
+            NewTrade{
+                log,
+                amm:TradePool::RayClmm(RayClmm{
+                    pool: accounts[2],
+                    amm_config: accounts[1],
+                    observation: accounts[7],
+                    token_0_mint: token_0.1,
+                    token_1_mint: token_1.1,
+                    token_0_vault: token_0.0,
+                    token_1_vault: token_1.0,
+                    decimals: None,
+                    trade_fee_rate: None,
+                    tick_spacing: None,
+                    tick_current: None,
+                    tick_array_bitmap: None,
+                }),
+                user_wallet: payer,
+            }
+        )
+    }
+
     pub fn with_pump_swap(self, accounts: &[Pubkey], log: String) -> anyhow::Result<Self> {
         let token_program_id = accounts[8].to_string();
         let amm = accounts[3].to_string();
@@ -920,13 +2196,83 @@ impl Trade {
     }
 }
 
+impl TradeState {
+    /// Legal moves, resending a pending swap keeps its state
+    pub fn can_transition(self, to: TradeState) -> bool {
+        use TradeState::*;
+        match (self, to) {
+            (Buy, PendingBuy | BuyFailed) => true,
+            (PendingBuy, PendingBuy | BuySuccess | PartiallyClosed | BuyFailed) => true,
+            (BuySuccess | PartiallyClosed, Buy | PendingSell) => true,
+            (PendingSell, PendingSell | PartiallyClosed | SellSuccess | SellFailed) => true,
+            (SellFailed, PendingSell) => true,
+            _ => false,
+        }
+    }
+}
+
 impl TradeInstruction {
     pub fn instructions(&self)->&Vec<Instruction>{
         match self {
             TradeInstruction::Jito(v) => v,
-            TradeInstruction::General(v) => v
+            TradeInstruction::General(v) => v,
+            TradeInstruction::JitoTip(v) => v,
         }
     }
+    pub fn instructions_mut(&mut self)->&mut Vec<Instruction>{
+        match self {
+            TradeInstruction::Jito(v) => v,
+            TradeInstruction::General(v) => v,
+            TradeInstruction::JitoTip(v) => v,
+        }
+    }
+}
+
+/// Names the failure from the simulated logs, slippage and balance errors are the usual ones
+fn simulation_error(err: &str, logs: &[String]) -> String {
+    let line = logs.iter().find(|x| {
+        let x = x.to_lowercase();
+        x.contains("slippage")
+            || x.contains("toomuchsolrequired")
+            || x.contains("toolittlesolreceived")
+            || x.contains("insufficient")
+            || x.contains("error")
+    });
+    let lower = line.map(|x| x.to_lowercase()).unwrap_or_default();
+    let kind = if lower.contains("slippage")
+        || lower.contains("toomuchsolrequired")
+        || lower.contains("toolittlesolreceived")
+    {
+        "slippage"
+    } else if lower.contains("insufficient") {
+        "balance"
+    } else {
+        "simulation"
+    };
+    format!("{kind} error {err} {}", line.map(|x| x.as_str()).unwrap_or(""))
+}
+
+/// Moves `lamports` into the wallet's WSOL account for a pool that takes wrapped SOL,
+/// nothing but the create when selling
+fn wrap_sol(pubkey: &Pubkey, wsol: &Pubkey, lamports: u64) -> Vec<Instruction> {
+    let mut instructions = vec![
+        spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+            pubkey,
+            pubkey,
+            &SOLANA_MINT,
+            &spl_token::id(),
+        ),
+    ];
+    if lamports > 0 {
+        instructions.push(solana_sdk::system_instruction::transfer(pubkey, wsol, lamports));
+        instructions.push(spl_token::instruction::sync_native(&spl_token::id(), wsol).unwrap());
+    }
+    instructions
+}
+
+/// Closes the WSOL account after the swap, what's left of a buy and a sell's proceeds come back as SOL
+fn unwrap_sol(pubkey: &Pubkey, wsol: &Pubkey) -> Instruction {
+    spl_token::instruction::close_account(&spl_token::id(), wsol, pubkey, pubkey, &[pubkey]).unwrap()
 }
 
 fn build_memo_instruction() -> Instruction {
@@ -944,10 +2290,11 @@ fn build_memo_instruction() -> Instruction {
 }
 
 impl TradeTransaction {
-    pub fn transaction(&self) ->&Transaction{
+    pub fn transaction(&self) ->&VersionedTransaction{
         match self {
             TradeTransaction::Jito(v) => v,
-            TradeTransaction::General(v) => v
+            TradeTransaction::General(v) => v,
+            TradeTransaction::JitoTip(v) => v,
         }
     }
 }
@@ -987,6 +2334,33 @@ impl TradeRpcLog {
         };
         Signature::from_str(s.as_str()).unwrap()
     }
+    pub fn bundle_id(&self)->Option<&String>{
+        match self {
+            TradeRpcLog::BuyJito(v,_) => v.bundle_id.as_ref(),
+            TradeRpcLog::SellJito(v,_) => v.bundle_id.as_ref(),
+            _ => None
+        }
+    }
+    pub fn set_bundle_status(&mut self, bundle_id:&str, status:String){
+        match self {
+            TradeRpcLog::BuyJito(v,_) | TradeRpcLog::SellJito(v,_) => {
+                if v.bundle_id.as_deref() == Some(bundle_id) {
+                    v.bundle_status = Some(status);
+                }
+            }
+            _ => {}
+        }
+    }
+    pub fn pending_signature(&self)->Option<Signature>{
+        let s = match self {
+            TradeRpcLog::BuyJito(v,TradeRpcLogStatus::Pending) => Some(v.general.signature.to_string()),
+            TradeRpcLog::SellJito(v,TradeRpcLogStatus::Pending) => Some(v.general.signature.to_string()),
+            TradeRpcLog::BuyGeneral(v,TradeRpcLogStatus::Pending) => Some(v.signature.to_string()),
+            TradeRpcLog::SellGeneral(v,TradeRpcLogStatus::Pending) => Some(v.signature.to_string()),
+            _ => None
+        }.map(|x|Signature::from_str(x.as_str()).unwrap());
+        s
+    }
     pub fn success_signature(&self)->Option<Signature>{
         let s = match self {
             TradeRpcLog::BuyJito(v,TradeRpcLogStatus::Success) => Some(v.general.signature.to_string()),
@@ -1004,6 +2378,9 @@ impl TradePool {
         match self {
             TradePool::RayAmm4(v) => v.amm.to_string(),
             TradePool::PumpBondingCurve(v) => v.amm.to_string(),
+            TradePool::RayCpmm(v) => v.pool.to_string(),
+            TradePool::RayClmm(v) => v.pool.to_string(),
+            TradePool::PumpAmm(v) => v.pool.to_string(),
             _ => unreachable!()
         }
     }
@@ -1011,6 +2388,9 @@ impl TradePool {
         match self {
             TradePool::RayAmm4(v) => v.amm,
             TradePool::PumpBondingCurve(v) => v.amm,
+            TradePool::RayCpmm(v) => v.pool,
+            TradePool::RayClmm(v) => v.pool,
+            TradePool::PumpAmm(v) => v.pool,
             _ => unreachable!()
         }
     }
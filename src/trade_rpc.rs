@@ -1,5 +1,5 @@
 use crate::constant::*;
-use crate::trade::{RpcInfo, RpcsConfig, TipStatistics, TradeRpc};
+use crate::trade::{RpcInfo, RpcsConfig, TipStatistics, Trade, TradeRpc};
 use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_sdk::commitment_config::CommitmentConfig;
 
@@ -54,6 +54,25 @@ pub fn rpc3() -> TradeRpc {
 }
 
 const JITO_NAME:&'static str = "JITO";
+const PAPER_NAME:&'static str = "PAPER";
+
+pub fn paper() -> TradeRpc {
+    TradeRpc::Paper {
+        info: RpcInfo{
+            name: PAPER_NAME.to_string(),
+            location: "local".to_string(),
+        }
+    }
+}
+
+/// Paper trades never touch the network
+pub fn rpcs_for(trade: &Trade, config:RpcsConfig) -> Vec<TradeRpc> {
+    if trade.paper {
+        vec![paper()]
+    } else {
+        rpcs(config)
+    }
+}
 
 pub fn jito1(tip: Option<TipStatistics>) -> TradeRpc {
     TradeRpc::Jito {
@@ -77,9 +96,18 @@ pub fn jito2(tip: Option<TipStatistics>) -> TradeRpc {
     }
 }
 
-
-
-
-
-
-
+/// `confirmation_status` of a jito bundle, the error once it failed, none while the block engine doesn't know it
+pub async fn jito_bundle_status(bundle_id: &str) -> anyhow::Result<Option<String>> {
+    let rpc = jito_sdk_rust::JitoJsonRpcSDK::new(JITO1, None);
+    let resp = rpc.get_bundle_statuses(vec![bundle_id.to_string()]).await?;
+    let status = &resp["result"]["value"][0];
+    if status.is_null() {
+        return Ok(None);
+    }
+    // landed bundles have {"Ok": null}
+    let err = &status["err"];
+    if !err.is_null() && err.get("Ok").is_none() {
+        return Ok(Some(format!("failed {err}")));
+    }
+    Ok(status["confirmation_status"].as_str().map(|x| x.to_string()))
+}
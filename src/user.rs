@@ -1,7 +1,9 @@
 use mongodb::Collection;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use solana_sdk::signature::Keypair;
 use crate::implement_mongo_crud_struct;
+use crate::wallet::WalletSelection;
 
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct UserWithId {
@@ -9,7 +11,18 @@ pub struct UserWithId {
     pub private_key: String,
     pub username: String,
     pub password: String,
-    pub admin:bool
+    pub admin:bool,
+    /// default strategy for this user's trades
+    pub strategy: Option<String>,
+    /// more wallets as base58 private keys, `private_key` is always the first wallet
+    #[serde(default)]
+    pub wallets: Vec<String>,
+    /// wallet pick for trades that don't name one
+    #[serde(default)]
+    pub wallet_selection: WalletSelection,
+    /// round robin counter, see `UserWithId::next_wallet_turn`
+    #[serde(default)]
+    pub wallet_turn: i64,
 }
 
 implement_mongo_crud_struct!(UserWithId);
@@ -23,6 +36,24 @@ impl UserWithId {
             .await.unwrap();
         user
     }
+    /// `private_key` then `wallets`
+    pub fn keypairs(&self) -> Vec<Keypair> {
+        std::iter::once(&self.private_key)
+            .chain(self.wallets.iter())
+            .map(|x| Keypair::from_base58_string(x))
+            .collect()
+    }
+    /// Increments the round robin counter and returns its previous value
+    pub async fn next_wallet_turn(col:&Collection<Self>, id: i64) -> anyhow::Result<i64> {
+        let user = col
+            .find_one_and_update(
+                mongodb::bson::doc! { "id": id },
+                mongodb::bson::doc! { "$inc": { "wallet_turn": 1_i64 } },
+            )
+            .await?
+            .ok_or(anyhow::anyhow!("unknown user {id}"))?;
+        Ok(user.wallet_turn)
+    }
     pub async fn get_by_user_name(col:&Collection<Self>, username: &str) -> Option<UserWithId> {
         let user = col
             .find_one(mongodb::bson::doc! { "username": username })
@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use anyhow::anyhow;
+use mongodb::Collection;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Keypair;
+use solana_sdk::signer::Signer;
+use solana_sdk::system_instruction::transfer;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use crate::chan::Chan;
+use crate::lookup_table::send_and_confirm;
+use crate::trade::{Trade, TradeState};
+use crate::trade_cmd::InternalCommand;
+use crate::trade_rpc::solana_rpc_client;
+use crate::user::UserWithId;
+
+/// fee of a single signature transfer, left behind when sweeping
+const TRANSFER_FEE: u64 = 5000;
+
+/// How a new trade picks the wallet that signs it
+#[derive(Clone,Debug,Serialize,Deserialize,JsonSchema,Default,PartialEq,Eq)]
+pub enum WalletSelection {
+    /// next wallet in turn
+    #[default]
+    RoundRobin,
+    /// wallet with the least SOL in open trades
+    LeastExposure,
+    /// this wallet's pubkey
+    Wallet(String),
+}
+
+/// SOL in the wallet's open positions at cost and in its buys in flight, see [Trade::exposure]
+pub fn exposure(trades: &[Trade], wallet: &Pubkey) -> Decimal {
+    trades
+        .iter()
+        .filter(|x| x.root_kp().pubkey() == *wallet)
+        .map(|x| x.exposure())
+        .sum()
+}
+
+/// Trades on `wallets` that are not closed yet. The engine's cache is asked too, limit
+/// orders and buys that haven't landed are only there.
+pub async fn open_trades(trade_db: &Collection<Trade>, chan: &Chan, wallets: &[Pubkey]) -> Vec<Trade> {
+    let mut trades = Trade::db_get_active_by_wallets(trade_db, wallets).await;
+    let (s, r) = tokio::sync::oneshot::channel();
+    if chan.trade.try_send(InternalCommand::CachedTrades(s)).is_ok() {
+        if let Ok(InternalCommand::CachedTradesResponse(cached)) = r.await {
+            let cached = cached
+                .into_iter()
+                .filter(|x| wallets.contains(&x.root_kp().pubkey()))
+                .collect::<Vec<_>>();
+            // the cache is ahead of the db
+            trades.retain(|x| !cached.iter().any(|c| c.id == x.id));
+            trades.extend(cached);
+        }
+    }
+    trades.retain(|x| !matches!(x.state, TradeState::BuyFailed | TradeState::SellSuccess));
+    trades
+}
+
+/// Picks `count` distinct wallets for a trade on `mint`. Wallets already in a trade on the
+/// same mint are only used when there are not enough others, so they don't race each other.
+pub async fn pick_wallets(
+    user: &UserWithId,
+    users: &Collection<UserWithId>,
+    trade_db: &Collection<Trade>,
+    chan: &Chan,
+    mint: &str,
+    selection: &WalletSelection,
+    count: usize,
+) -> anyhow::Result<Vec<Keypair>> {
+    let wallets = user.keypairs();
+    if count == 0 || count > wallets.len() {
+        return Err(anyhow!("{count} wallets requested, user has {}", wallets.len()));
+    }
+    if let WalletSelection::Wallet(pubkey) = selection {
+        if count > 1 {
+            return Err(anyhow!("an explicit wallet can't be split"));
+        }
+        return wallets
+            .into_iter()
+            .find(|x| x.pubkey().to_string() == *pubkey)
+            .map(|x| vec![x])
+            .ok_or(anyhow!("unknown wallet {pubkey}"));
+    }
+
+    let pubkeys = wallets.iter().map(|x| x.pubkey()).collect::<Vec<_>>();
+    let active = open_trades(trade_db, chan, &pubkeys).await;
+    let on_mint = active
+        .iter()
+        .filter(|x| x.mint().to_string() == mint)
+        .map(|x| x.root_kp().pubkey())
+        .collect::<Vec<_>>();
+    let (mut free, busy): (Vec<_>, Vec<_>) = wallets
+        .into_iter()
+        .partition(|x| !on_mint.contains(&x.pubkey()));
+
+    match selection {
+        WalletSelection::RoundRobin => {
+            let turn = UserWithId::next_wallet_turn(users, user.id).await? as usize;
+            let len = free.len().max(1);
+            free.rotate_left(turn % len);
+        }
+        WalletSelection::LeastExposure => {
+            let exposures = free
+                .iter()
+                .map(|x| (x.pubkey(), exposure(&active, &x.pubkey())))
+                .collect::<HashMap<_, _>>();
+            free.sort_by_key(|x| exposures[&x.pubkey()]);
+        }
+        WalletSelection::Wallet(_) => unreachable!(),
+    }
+    free.extend(busy);
+    free.truncate(count);
+    Ok(free)
+}
+
+pub async fn balance(wallet: &Pubkey) -> anyhow::Result<u64> {
+    Ok(solana_rpc_client().get_balance(wallet).await?)
+}
+
+/// Moves `sol_ui` from one of the user's wallets to another
+pub async fn fund(from: &Keypair, to: &Pubkey, sol_ui: Decimal) -> anyhow::Result<u64> {
+    let lamports = (sol_ui * Decimal::from(LAMPORTS_PER_SOL))
+        .to_u64()
+        .ok_or(anyhow!("bad amount {sol_ui}"))?;
+    send_and_confirm(from, &[transfer(&from.pubkey(), to, lamports)]).await?;
+    Ok(lamports)
+}
+
+/// Moves every lamport but the fee from `from` into `to`, returns the lamports moved by wallet.
+/// Wallets in an open trade are skipped by the caller, sweeping them would starve their exits.
+pub async fn sweep(from: &[Keypair], to: &Pubkey) -> Vec<(Pubkey, anyhow::Result<u64>)> {
+    let mut swept = vec![];
+    for wallet in from.iter().filter(|x| x.pubkey() != *to) {
+        let r = async {
+            let lamports = balance(&wallet.pubkey()).await?.saturating_sub(TRANSFER_FEE);
+            if lamports > 0 {
+                send_and_confirm(wallet, &[transfer(&wallet.pubkey(), to, lamports)]).await?;
+            }
+            Ok::<_, anyhow::Error>(lamports)
+        }
+        .await;
+        swept.push((wallet.pubkey(), r));
+    }
+    swept
+}